@@ -1,264 +1,2631 @@
 use std;
 use std::env;
-use std::fs::File;
-use std::io::Write;
-use std::io::{BufRead, BufReader};
+use std::io::{IsTerminal, Write};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
 use std::process;
 
 use clap::*;
-use env_logger::Builder;
 use log::LevelFilter;
 use bird_tool_utils_man;
-use bird_tool_utils_man::prelude::{Opt, Section, Manual};
+use bird_tool_utils_man::prelude::{Flag, Opt, Section, Manual};
 use tempfile;
 
+use crate::genome_directory::*;
+use crate::genome_download::*;
+use crate::genome_specification::*;
+use crate::logging::*;
+
+/// Set up logging according to --verbose, --quiet and --log-level, as added
+/// by add_clap_verbosity_flags(), and --log-file, as added by
+/// add_clap_logging_flags(). Each --verbose increases the level from info,
+/// to debug and then trace, and --quiet only logs errors, or nothing if given
+/// twice. --log-level sets the level directly. If none are given, the level is read from the
+/// BIRD_TOOL_LOG_LEVEL environment variable if it is set (see
+/// LoggingOptions::log_level_env_var), e.g. 'debug', for use where the
+/// command line cannot be changed. If it is not a log level, a warning is
+/// logged and it is ignored. Tools which declare these flags themselves
+/// as booleans are also supported. The logger is only initialised if a flag was given or
+/// is_last, so that in tools with subcommands this can be called first for
+/// the top level matches and then for the subcommand's. If logging has
+/// already been set up, a warning is logged. If the log file cannot be
+/// opened, the problem is printed and the process exits with status 1.
 pub fn set_log_level(matches: &clap::ArgMatches, is_last: bool, program_name: &str, version: &str) {
-    let mut log_level = LevelFilter::Info;
-    let mut specified = false;
-    if matches.get_flag("verbose") {
-        specified = true;
-        log_level = LevelFilter::Debug;
+    set_log_level_with_options(
+        matches,
+        is_last,
+        program_name,
+        version,
+        &LoggingOptions::default(),
+    )
+}
+
+/// As set_log_level(), with the format of log output controlled by options.
+pub fn set_log_level_with_options(
+    matches: &clap::ArgMatches,
+    is_last: bool,
+    program_name: &str,
+    version: &str,
+    options: &LoggingOptions,
+) {
+    match try_set_log_level_with_options(matches, is_last, program_name, version, options) {
+        Ok(()) => {}
+        Err(SetLogLevelError::AlreadyInitialised(_)) => {
+            warn!("Not setting log level, as logging has already been set up")
+        }
+        Err(e @ SetLogLevelError::InvalidLogLevel { .. }) => {
+            set_log_level_with_options(
+                matches,
+                is_last,
+                program_name,
+                version,
+                &LoggingOptions {
+                    log_level_env_var: None,
+                    ..options.clone()
+                },
+            );
+            warn!("{}, so it was ignored", e)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// As set_log_level(), with the levels of log records from modules starting
+/// with the given prefixes set separately, e.g. &[("hyper",
+/// LevelFilter::Warn)] so that debug output from a noisy dependency does not
+/// bury that of the tool itself. RUST_LOG still overrides these.
+pub fn set_log_level_with_filters(
+    matches: &clap::ArgMatches,
+    is_last: bool,
+    program_name: &str,
+    version: &str,
+    module_filters: &[(&str, LevelFilter)],
+) {
+    set_log_level_with_options(
+        matches,
+        is_last,
+        program_name,
+        version,
+        &LoggingOptions {
+            module_filters: module_filters
+                .iter()
+                .map(|(module, level)| (module.to_string(), *level))
+                .collect(),
+            ..Default::default()
+        },
+    )
+}
+
+/// As set_log_level(), except that an Err is returned if logging cannot be
+/// set up, rather than exiting, or if the log level environment variable is
+/// not a log level, rather than ignoring it. If a logger has already been
+/// installed, SetLogLevelError::AlreadyInitialised is returned, which callers
+/// can usually ignore. As with set_log_level(), nothing is done unless a logging
+/// flag was given or is_last, so for tools with subcommands it is only an Err
+/// if both the top level and the subcommand specify logging flags.
+pub fn try_set_log_level(
+    matches: &clap::ArgMatches,
+    is_last: bool,
+    program_name: &str,
+    version: &str,
+) -> std::result::Result<(), SetLogLevelError> {
+    try_set_log_level_with_options(
+        matches,
+        is_last,
+        program_name,
+        version,
+        &LoggingOptions::default(),
+    )
+}
+
+/// As try_set_log_level(), with the format of log output controlled by
+/// options.
+pub fn try_set_log_level_with_options(
+    matches: &clap::ArgMatches,
+    is_last: bool,
+    program_name: &str,
+    version: &str,
+    options: &LoggingOptions,
+) -> std::result::Result<(), SetLogLevelError> {
+    let verbose = flag_count(matches, "verbose");
+    let quiet = flag_count(matches, "quiet");
+    let log_level_flag = match matches.try_get_one::<String>("log-level") {
+        Ok(Some(level)) => level.parse::<LevelFilter>().ok(),
+        _ => None,
+    };
+    let log_file = match matches.try_get_one::<String>("log-file") {
+        Ok(Some(path)) => Some(PathBuf::from(path)),
+        _ => None,
+    };
+    let specified = verbose > 0 || quiet > 0 || log_level_flag.is_some() || log_file.is_some();
+    if !(specified || is_last) {
+        return Ok(());
+    }
+    let (log_level, level_source) =
+        select_log_level(log_level_flag, quiet, verbose, options.log_level_env_var.as_deref())?;
+    let mut options = options.clone();
+    if log_file.is_some() {
+        options.log_file = log_file;
+        options.log_file_append = flag_is_set(matches, "log-file-append");
+    }
+    match matches.try_get_one::<String>("log-format") {
+        Ok(Some(format)) if format == "json" => options.format = LogFormat::Json,
+        Ok(Some(_)) => options.format = LogFormat::Text,
+        _ => {}
+    }
+    if flag_is_set(matches, "log-threads") {
+        options.show_thread = true;
     }
-    if matches.get_flag("quiet") {
-        specified = true;
-        log_level = LevelFilter::Error;
+    if flag_is_set(matches, "log-elapsed") {
+        options.elapsed = true;
     }
-    if specified || is_last {
-        let mut builder = Builder::new();
-        builder.filter_level(log_level);
-        if env::var("RUST_LOG").is_ok() {
-            builder.parse_filters(&env::var("RUST_LOG").unwrap());
+    if flag_is_set(matches, "no-color") {
+        options.color = LogColor::Never;
+    } else {
+        match matches.try_get_one::<String>("color") {
+            Ok(Some(color)) if color == "always" => options.color = LogColor::Always,
+            Ok(Some(color)) if color == "never" => options.color = LogColor::Never,
+            _ => {}
         }
-        if builder.try_init().is_err() {
-            panic!("Failed to set log level - has it been specified multiple times? Error was {}", builder.try_init().unwrap_err())
+    }
+    init_logging(log_level, program_name, version, &options, is_last)?;
+    debug!(
+        "Log level is {}, {}. RUST_LOG takes precedence over --log-level, --verbose and --quiet{}",
+        log_level,
+        level_source,
+        match &options.log_level_env_var {
+            Some(variable) => format!(", which take precedence over {}", variable),
+            None => "".to_string(),
         }
+    );
+    Ok(())
+}
+
+/// The log level set by the environment variable, if it is set.
+/// The log level given by --log-level, or otherwise the number of times
+/// --quiet or --verbose were given, or if none were given, by the
+/// log_level_env_var environment variable or by default, along with where the
+/// level came from for logging.
+fn select_log_level(
+    log_level_flag: Option<LevelFilter>,
+    quiet: u8,
+    verbose: u8,
+    log_level_env_var: Option<&str>,
+) -> std::result::Result<(LevelFilter, String), SetLogLevelError> {
+    Ok(match (log_level_flag, quiet, verbose) {
+        (Some(level), _, _) => (level, "as set by --log-level".to_string()),
+        (None, 0, 0) => match log_level_env_var {
+            Some(variable) => match env_log_level(variable)? {
+                Some(level) => (level, format!("as set by {}", variable)),
+                None => (LevelFilter::Info, "by default".to_string()),
+            },
+            None => (LevelFilter::Info, "by default".to_string()),
+        },
+        (None, 0, 1) => (LevelFilter::Debug, "as set by --verbose".to_string()),
+        (None, 0, _) => (LevelFilter::Trace, "as set by --verbose".to_string()),
+        (None, 1, _) => (LevelFilter::Error, "as set by --quiet".to_string()),
+        (None, _, _) => (LevelFilter::Off, "as set by --quiet".to_string()),
+    })
+}
+
+fn env_log_level(variable: &str) -> std::result::Result<Option<LevelFilter>, SetLogLevelError> {
+    match env::var(variable) {
+        Ok(value) => match value.parse::<LevelFilter>() {
+            Ok(level) => Ok(Some(level)),
+            Err(_) => Err(SetLogLevelError::InvalidLogLevel {
+                variable: variable.to_string(),
+                value,
+            }),
+        },
+        Err(_) => Ok(None),
     }
-    if is_last {
-        info!("{} version {}", program_name, version);
+}
+
+/// Number of times a verbosity flag was given. Flags declared as booleans
+/// rather than with add_clap_verbosity_flags() count once.
+fn flag_count(matches: &clap::ArgMatches, id: &str) -> u8 {
+    match matches.try_get_one::<u8>(id) {
+        Ok(Some(count)) => *count,
+        _ => flag_is_set(matches, id) as u8,
     }
 }
 
+/// Display the manual if --full-help was given, or print it as roff to stdout
+/// if --full-help-roff was given, and then exit. If the tool also defines
+/// --full-help-markdown or --full-help-html flags, the manual is printed as
+/// Markdown (see render_manual_markdown()) or HTML (see render_manual_html())
+/// when they are given. Similarly, if --generate-man-page was added with
+/// add_generate_man_page_flag(), the roff is written to the given path,
+/// compressed with gzip if it ends in '.gz'. If any options are hidden from
+/// --help, e.g. with GenomeSpecArgsConfig::hidden, a NOTES section saying so is
+/// added to the manual.
 pub fn print_full_help_if_needed(m: &clap::ArgMatches, manual: Manual) {
+    print_full_help_if_needed_lazy(m, || manual)
+}
+
+/// As print_full_help_if_needed(), but the manual is only made, by calling
+/// manual_fn, if one of the flags was given, as making it can take a
+/// noticeable amount of time for tools with long manuals.
+pub fn print_full_help_if_needed_lazy<F: FnOnce() -> Manual>(m: &clap::ArgMatches, manual_fn: F) {
+    match print_full_help_if_needed_lazy_noexit(m, manual_fn) {
+        Ok(true) => process::exit(0),
+        Ok(false) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// As print_full_help_if_needed(), but rather than exiting, return Ok(true)
+/// if one of the flags was given and the manual shown, or Ok(false) if none
+/// were given, so the caller can decide whether to return or exit. An Err is
+/// returned if the manual could not be shown or written. Stdout is flushed
+/// before returning.
+pub fn print_full_help_if_needed_noexit(
+    m: &clap::ArgMatches,
+    manual: Manual,
+) -> std::result::Result<bool, String> {
+    print_full_help_if_needed_lazy_noexit(m, || manual)
+}
+
+/// As print_full_help_if_needed_noexit(), but the manual is only made if
+/// needed, as for print_full_help_if_needed_lazy().
+pub fn print_full_help_if_needed_lazy_noexit<F: FnOnce() -> Manual>(
+    m: &clap::ArgMatches,
+    manual_fn: F,
+) -> std::result::Result<bool, String> {
+    let mut stdout = std::io::stdout();
+    let is_terminal = stdout.is_terminal();
+    print_full_help_if_needed_to(m, manual_fn, &mut stdout, is_terminal, &FullHelpEnv::from_env())
+}
+
+/// As print_full_help_if_needed_lazy_noexit(), but printing to out rather than
+/// stdout, and running 'man' and pagers in full_help_env if out is_terminal.
+fn print_full_help_if_needed_to<F: FnOnce() -> Manual>(
+    m: &clap::ArgMatches,
+    manual_fn: F,
+    out: &mut dyn Write,
+    is_terminal: bool,
+    full_help_env: &FullHelpEnv,
+) -> std::result::Result<bool, String> {
     if m.get_flag("full-help") {
-        display_full_help(manual)
+        let roff = full_help_roff(manual_fn());
+        if is_terminal {
+            display_full_help_roff_with_env(&roff, out, full_help_env)?;
+        } else {
+            debug!("Printing --full-help as plain text as stdout is not a terminal");
+            write_full_help_text(out, &plain_text_from_roff(&roff, full_help_width()))?;
+        }
     } else if m.get_flag("full-help-roff") {
-        println!("{}", manual.render());
-        process::exit(0);
+        write_full_help_text(out, &format!("{}\n", full_help_roff(manual_fn())))?;
+    } else if flag_is_set(m, "full-help-markdown") {
+        write_full_help_text(out, &markdown_from_roff(&full_help_roff(manual_fn())))?;
+    } else if flag_is_set(m, "full-help-html") {
+        write_full_help_text(out, &html_from_roff(&full_help_roff(manual_fn())))?;
+    } else if let Ok(Some(path)) = m.try_get_one::<String>("generate-man-page") {
+        let path = Path::new(path);
+        let gzip = path.extension().is_some_and(|extension| extension == "gz");
+        write_roff_to_file(&full_help_roff(manual_fn()), path, gzip).map_err(|e| {
+            format!("Failed to write man page to '{}': {}", path.display(), e)
+        })?;
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Appended to the man page help of options hidden from --help.
+const HIDDEN_OPTION_NOTE: &str = "Not shown by --help.";
+
+/// Render the manual for print_full_help_if_needed(), adding a NOTES section
+/// at the end if any options are hidden from --help, so that users know there
+/// is more to the tool than --help shows.
+fn full_help_roff(manual: Manual) -> String {
+    let mut roff = manual.render();
+    // The Manual escapes hyphens when it is rendered.
+    let note = HIDDEN_OPTION_NOTE.replace('-', "\\-");
+    if roff.contains(&note) {
+        let notes = format!(
+            "\n.SH NOTES\nSome options are not shown by \\-\\-help, and are marked '{}' above.",
+            note
+        );
+        // Put the notes before the sections manual_skeleton() adds at the end,
+        // as AUTHOR leaves no-fill mode on.
+        let index = ["\n.SH EXIT STATUS\n", "\n.SH AUTHOR\n"]
+            .iter()
+            .find_map(|heading| roff.find(heading))
+            .unwrap_or(roff.trim_end().len());
+        roff.insert_str(index, &notes);
     }
+    roff
 }
 
 /// Parse clap arguments defined in the common way, returning a list of paths as
 /// strings. If fail_on_no_genomes, return an Err if no genomes were detected.
+/// Genomes found in a --genome-fasta-directory are returned sorted by path,
+/// whereas those given with --genome-fasta-files or --genome-fasta-list are
+/// returned in the order the user specified them.
+/// Paths which are not valid UTF-8 are skipped with a warning - use
+/// parse_list_of_genome_fasta_paths() to receive them.
 pub fn parse_list_of_genome_fasta_files(
     m: &clap::ArgMatches,
     fail_on_no_genomes: bool,
 ) -> std::result::Result<Vec<String>, String> {
-    match m.contains_id("genome-fasta-files") {
+    parse_list_of_genome_fasta_files_with_min_genomes(m, fail_on_no_genomes as usize)
+}
+
+/// As parse_list_of_genome_fasta_files(), except that an Err is returned if
+/// fewer than min_genomes genomes were detected, saying how many were found.
+pub fn parse_list_of_genome_fasta_files_with_min_genomes(
+    m: &clap::ArgMatches,
+    min_genomes: usize,
+) -> std::result::Result<Vec<String>, String> {
+    parse_list_of_fasta_file_strings(
+        m,
+        &GenomeSpecificationOptions {
+            min_genomes,
+            ..Default::default()
+        },
+    )
+}
+
+/// As parse_list_of_genome_fasta_files(), but for arguments added by
+/// add_fasta_specification_arguments() with prefix.
+pub fn parse_list_of_fasta_files(
+    m: &clap::ArgMatches,
+    prefix: &str,
+    fail_on_no_genomes: bool,
+) -> std::result::Result<Vec<String>, String> {
+    parse_list_of_fasta_file_strings(
+        m,
+        &GenomeSpecificationOptions {
+            fail_on_no_genomes,
+            argument_prefix: Some(prefix.to_string()),
+            ..Default::default()
+        },
+    )
+}
+
+fn parse_list_of_fasta_file_strings(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<String>, String> {
+    let paths =
+        parse_list_of_genome_fasta_paths_with_options(m, options).map_err(|e| e.to_string())?;
+    let mut genome_fasta_files = Vec::with_capacity(paths.len());
+    for path in paths {
+        match path.into_os_string().into_string() {
+            Ok(s) => genome_fasta_files.push(s),
+            Err(s) => warn!(
+                "Not using genome FASTA file '{}' as its path is not valid UTF-8",
+                s.to_string_lossy()
+            ),
+        }
+    }
+    Ok(genome_fasta_files)
+}
+
+/// As parse_list_of_genome_fasta_files(), except that on error the problem is
+/// logged along with a reminder of how genomes can be specified, and the
+/// process exits with status 1.
+pub fn parse_list_of_genome_fasta_files_or_exit(
+    m: &clap::ArgMatches,
+    fail_on_no_genomes: bool,
+) -> Vec<String> {
+    match parse_list_of_genome_fasta_files(m, fail_on_no_genomes) {
+        Ok(genome_fasta_files) => genome_fasta_files,
+        Err(e) => {
+            error!("{}", e);
+            error!(
+                "Genomes can be specified with --genome-fasta-files, \
+                --genome-fasta-directory, --genome-fasta-list or --genome-fasta-archive"
+            );
+            log::logger().flush();
+            process::exit(1);
+        }
+    }
+}
+
+/// Parse the genome given with --single-genome-fasta, returning an Err if a
+/// genome was not given, or was given with a multi-genome option such as
+/// --genome-fasta-directory instead.
+pub fn parse_single_genome_fasta_file(
+    m: &clap::ArgMatches,
+) -> std::result::Result<String, String> {
+    parse_single_genome_fasta_file_with_options(m, false)
+}
+
+/// As parse_single_genome_fasta_file(), except that if
+/// accept_one_of_multiple, a multi-genome option which specifies exactly one
+/// genome is accepted with a warning.
+pub fn parse_single_genome_fasta_file_with_options(
+    m: &clap::ArgMatches,
+    accept_one_of_multiple: bool,
+) -> std::result::Result<String, String> {
+    if let Ok(Some(path)) = m.try_get_one::<String>("single-genome-fasta") {
+        return Ok(path.clone());
+    }
+    let multi_genome_option = [
+        "genome-fasta-files",
+        "genome-fasta-directory",
+        "genome-fasta-list",
+        "genome-fasta-archive",
+    ]
+    .iter()
+    .find(|id| m.contains_id(id));
+    match (multi_genome_option, accept_one_of_multiple) {
+        (None, _) => Err("A genome must be specified with --single-genome-fasta".to_string()),
+        (Some(id), false) => Err(format!(
+            "Only a single genome can be used, so please specify it with \
+            --single-genome-fasta rather than --{}",
+            id
+        )),
+        (Some(id), true) => {
+            let genome_fasta_files = parse_list_of_genome_fasta_files(m, true)?;
+            match genome_fasta_files.len() {
+                1 => {
+                    warn!(
+                        "Using the single genome specified with --{}. Consider using \
+                        --single-genome-fasta instead",
+                        id
+                    );
+                    Ok(genome_fasta_files.into_iter().next().unwrap())
+                }
+                n => Err(format!(
+                    "Only a single genome can be used, but {} were specified with --{}",
+                    n, id
+                )),
+            }
+        }
+    }
+}
+
+/// Parse clap arguments defined in the common way, returning a list of paths.
+/// If fail_on_no_genomes, return an Err if no genomes were detected. Unlike
+/// parse_list_of_genome_fasta_files(), paths which are not valid UTF-8 are
+/// returned as-is, and errors are returned as a GenomeSpecificationError.
+pub fn parse_list_of_genome_fasta_paths(
+    m: &clap::ArgMatches,
+    fail_on_no_genomes: bool,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    parse_list_of_genome_fasta_paths_with_options(
+        m,
+        &GenomeSpecificationOptions {
+            fail_on_no_genomes,
+            ..Default::default()
+        },
+    )
+}
+
+/// Parse clap arguments defined in the common way, returning a list of paths,
+/// with discovery and checking of the genomes controlled by options. Genomes
+/// given with --genome-fasta-archive or as URLs are an Err, as they need
+/// temporary files - use parse_genome_fasta_files_with_options() for them.
+pub fn parse_list_of_genome_fasta_paths_with_options(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    Ok(find_and_check_genome_fasta_files(m, options, None)?
+        .into_iter()
+        .map(|f| f.path)
+        .collect())
+}
+
+/// Parse clap arguments defined in the common way, returning the set of paths,
+/// for tools which only need to know whether a genome was specified. Paths are
+/// iterated in sorted order regardless of how they were specified, and any
+/// that are specified more than once are only included once, with a warning.
+/// If fail_on_no_genomes, return an Err if no genomes were detected.
+pub fn parse_genome_fasta_file_set(
+    m: &clap::ArgMatches,
+    fail_on_no_genomes: bool,
+) -> std::result::Result<BTreeSet<PathBuf>, GenomeSpecificationError> {
+    // Repeated paths are removed with a warning by
+    // deduplicate_genome_fasta_files(), so only the order changes here.
+    Ok(parse_list_of_genome_fasta_paths(m, fail_on_no_genomes)?
+        .into_iter()
+        .collect())
+}
+
+/// Parse clap arguments defined in the common way, returning each genome FASTA
+/// file along with its genome name. Names are taken from the second column of
+/// --genome-fasta-list where given, and are otherwise derived from the file
+/// name (see genome_name_from_path()). Returns an Err if two genomes would have
+/// the same name.
+pub fn parse_genome_fasta_files_with_names(
+    m: &clap::ArgMatches,
+    fail_on_no_genomes: bool,
+) -> std::result::Result<Vec<GenomeFastaFile>, GenomeSpecificationError> {
+    let files = find_and_check_genome_fasta_files(
+        m,
+        &GenomeSpecificationOptions {
+            fail_on_no_genomes,
+            ..Default::default()
+        },
+        None,
+    )?;
+    check_genome_names_are_unique(&files)?;
+    Ok(files)
+}
+
+/// Parse clap arguments defined in the common way, returning each genome FASTA
+/// file along with the temporary directories genomes were extracted into from
+/// --genome-fasta-archive, or downloaded into when given as URLs. These files
+/// are deleted when the returned ParsedGenomeFastaFiles is dropped. Other
+/// functions which parse genome arguments return
+/// GenomeSpecificationError::TemporaryFilesNotSupported instead, as they
+/// cannot hand the temporary directories back.
+pub fn parse_genome_fasta_files_with_options(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<ParsedGenomeFastaFiles, GenomeSpecificationError> {
+    let mut temporary_directories = vec![];
+    let files = find_and_check_genome_fasta_files(m, options, Some(&mut temporary_directories))?;
+    Ok(ParsedGenomeFastaFiles {
+        files,
+        temporary_directories,
+    })
+}
+
+/// Find and check the genome FASTA files specified in m. Genomes extracted from
+/// archives or downloaded are put in temporary directories which are added to
+/// temporary_directories. If it is None, as the caller has no way of handing
+/// them back, genomes which need temporary files are an Err.
+fn find_and_check_genome_fasta_files(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+    mut temporary_directories: Option<&mut Vec<tempfile::TempDir>>,
+) -> std::result::Result<Vec<GenomeFastaFile>, GenomeSpecificationError> {
+    let mut files = find_genome_fasta_files(m, options, temporary_directories.as_deref_mut())?;
+    if let Some(max) = max_genomes(m, options) {
+        if files.len() > max {
+            return Err(GenomeSpecificationError::TooManyGenomes {
+                max_genomes: max,
+                found: files.len(),
+            });
+        }
+    }
+    let exclude = options.argument_id("genome-fasta-exclude");
+    if let Ok(Some(patterns)) = m.try_get_many::<String>(&exclude) {
+        let patterns: Vec<String> = patterns.cloned().collect();
+        let num_before = files.len();
+        files = exclude_genome_fasta_files(files, &patterns)?;
+        if files.is_empty() && num_before > 0 && options.required_genomes() > 0 {
+            return Err(GenomeSpecificationError::AllGenomesExcluded { patterns });
+        }
+    }
+    if options.allow_remote {
+        let temporary_directories = match temporary_directories {
+            Some(temporary_directories) => temporary_directories,
+            None if files.iter().any(|f| is_remote_genome_path(&f.path)) => {
+                return Err(GenomeSpecificationError::TemporaryFilesNotSupported {
+                    specification: "URLs".to_string(),
+                })
+            }
+            None => &mut vec![],
+        };
+        let (temporary_directory, local_files) = download_remote_genome_fasta_files(files)?;
+        files = local_files;
+        temporary_directories.extend(temporary_directory);
+    } else if let Some(file) = files.iter().find(|f| is_remote_genome_path(&f.path)) {
+        warn!(
+            "Genome FASTA path '{}' looks like a URL, but downloading genomes is not enabled",
+            file.path.display()
+        );
+    }
+    files = match options.canonicalize_paths {
+        true => canonicalize_genome_fasta_files(files)?,
+        false => deduplicate_genome_fasta_files(files),
+    };
+    if files.len() < options.required_genomes() {
+        return Err(GenomeSpecificationError::TooFewGenomes {
+            specification: genome_specification_argument(m, options),
+            min_genomes: options.required_genomes(),
+            found: files.len(),
+        });
+    }
+    if options.check_genome_names {
+        check_genome_names_are_unique(&files)?;
+    }
+    if options.log_summary {
+        info!("{}", summarize_genome_fasta_files(&files));
+    }
+    let paths: Vec<PathBuf> = files.iter().map(|f| f.path.clone()).collect();
+    check_genome_fasta_files(&paths, options)?;
+    Ok(files)
+}
+
+fn find_genome_fasta_files(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+    temporary_directories: Option<&mut Vec<tempfile::TempDir>>,
+) -> std::result::Result<Vec<GenomeFastaFile>, GenomeSpecificationError> {
+    let fail_on_no_genomes = options.required_genomes() > 0;
+    let id = |name: &str| options.argument_id(name);
+    match m.contains_id(&id("genome-fasta-files")) {
         true => {
-            return Ok(m
-                .get_many::<String>("genome-fasta-files")
-                .unwrap()
-                .map(|s| s.to_string())
+            let mut genome_fasta_files: Vec<PathBuf> = vec![];
+            for path in &genome_fasta_files_values(m, options)? {
+                if is_glob_pattern(path) {
+                    genome_fasta_files.extend(expand_genome_fasta_glob(path)?);
+                } else {
+                    genome_fasta_files.push(PathBuf::from(path));
+                }
+            }
+            let mut expanded = Vec::with_capacity(genome_fasta_files.len());
+            for path in genome_fasta_files {
+                match path.is_dir() {
+                    true => expanded.extend(genome_fasta_files_argument_directory(
+                        &path,
+                        &genome_fasta_extension_values(m, options),
+                        flag_is_set(m, &id("genome-fasta-directory-recursive")),
+                        options,
+                    )?),
+                    false => expanded.push(path),
+                }
+            }
+            Ok(expanded
+                .into_iter()
+                .map(GenomeFastaFile::from_path)
                 .collect())
         }
         false => {
-            if m.contains_id("genome-fasta-directory") {
-                let dir = m.get_one::<String>("genome-fasta-directory").unwrap();
-                let paths = std::fs::read_dir(dir)
-                    .expect(&format!("Failed to read genome-fasta-directory '{}'", dir));
-                let mut genome_fasta_files: Vec<String> = vec![];
-                let extension = m.get_one::<String>("genome-fasta-extension").unwrap();
-                // Remove leading dot if present
-                let extension2 = match extension.starts_with(".") {
-                    true => {
-                        extension.split_at(1).1
-                    },
-                    false => extension,
-                };
-                for path in paths {
-                    let file = path.unwrap().path();
-                    match file.extension() {
-                        Some(ext) => {
-                            if ext == extension2 {
-                                let s = String::from(file.to_string_lossy());
-                                genome_fasta_files.push(s);
-                            } else {
-                                info!(
-                                    "Not using directory entry '{}' as a genome FASTA file, as \
-                                     it does not end with the extension '.{}'",
-                                    file.to_str().expect("UTF8 error in filename"),
-                                    extension2
-                                );
-                            }
-                        }
-                        None => {
-                            info!(
-                                "Not using directory entry '{}' as a genome FASTA file",
-                                file.to_str().expect("UTF8 error in filename")
-                            );
+            if m.contains_id(&id("genome-fasta-directory")) {
+                let dirs: Vec<&Path> = m
+                    .get_many::<String>(&id("genome-fasta-directory"))
+                    .unwrap()
+                    .map(Path::new)
+                    .collect();
+                let recursive = flag_is_set(m, &id("genome-fasta-directory-recursive"));
+                let extensions = resolve_genome_fasta_extensions(
+                    &dirs,
+                    &genome_fasta_extension_values(m, options),
+                    recursive,
+                    options,
+                )?;
+                let max_genomes = max_genomes(m, options);
+                let mut genome_fasta_files = vec![];
+                let mut seen_paths = HashSet::new();
+                for dir in &dirs {
+                    for path in find_genome_fasta_files_in_directory(
+                        dir,
+                        &extensions,
+                        recursive,
+                        options,
+                        max_genomes.map(|max| (max, genome_fasta_files.len())),
+                    )? {
+                        if seen_paths.insert(path.clone()) {
+                            genome_fasta_files.push(path);
                         }
                     }
                 }
-                if genome_fasta_files.len() == 0 {
+                if genome_fasta_files.is_empty() {
                     return match fail_on_no_genomes {
                         true => std::result::Result::Err(
-                            "Found 0 genomes from the genome-fasta-directory, cannot continue."
-                                .to_string(),
+                            GenomeSpecificationError::EmptyDirectory {
+                                dirs: dirs.iter().map(|d| d.to_path_buf()).collect(),
+                                extensions_present: extensions_present(&dirs, recursive, options),
+                            },
                         ),
                         false => Ok(vec![]),
                     };
                 }
-                return Ok(genome_fasta_files);
-            } else if m.contains_id("genome-fasta-list") {
-                let file_path = m.get_one::<String>("genome-fasta-list").unwrap();
-                let file = File::open(file_path).expect(&format!(
-                    "Failed to open genome fasta list file {}",
-                    file_path
-                ));
-                let reader = BufReader::new(file);
-                let mut fasta_paths = vec![];
-                for (index, line) in reader.lines().enumerate() {
-                    let line = line.expect(&format!(
-                        "Error when reading genome fasta list file {} on line {}",
-                        file_path,
-                        index + 1
-                    ));
-                    // Show the line and its number.
-                    fasta_paths.push(line.trim().to_string());
-                }
-                return Ok(fasta_paths);
+                if dirs.len() > 1 {
+                    warn_about_duplicate_file_names(&genome_fasta_files);
+                }
+                Ok(genome_fasta_files
+                    .into_iter()
+                    .map(GenomeFastaFile::from_path)
+                    .collect())
+            } else if m.contains_id(&id("genome-fasta-archive")) {
+                let temporary_directories = temporary_directories.ok_or_else(|| {
+                    GenomeSpecificationError::TemporaryFilesNotSupported {
+                        specification: format!("--{}", id("genome-fasta-archive")),
+                    }
+                })?;
+                let archives: Vec<&String> = m
+                    .get_many::<String>(&id("genome-fasta-archive"))
+                    .unwrap()
+                    .collect();
+                let extensions = normalise_genome_fasta_extensions(
+                    &genome_fasta_extension_values(m, options),
+                    options,
+                );
+                let mut genome_fasta_files = vec![];
+                for archive in &archives {
+                    let (temporary_directory, archive_files) =
+                        extract_genome_fasta_archive(Path::new(archive), |member| {
+                            has_genome_fasta_extension(member, &extensions, options, "archive member")
+                        })?;
+                    info!(
+                        "Extracted {} genome FASTA file(s) from '{}'",
+                        archive_files.len(),
+                        archive
+                    );
+                    genome_fasta_files.extend(archive_files);
+                    temporary_directories.push(temporary_directory);
+                }
+                if genome_fasta_files.is_empty() && fail_on_no_genomes {
+                    return Err(GenomeSpecificationError::EmptyArchive {
+                        archives: archives.iter().map(PathBuf::from).collect(),
+                    });
+                }
+                Ok(genome_fasta_files
+                    .into_iter()
+                    .map(GenomeFastaFile::from_path)
+                    .collect())
+            } else if m.contains_id(&id("genome-fasta-list")) {
+                let file_path = m.get_one::<String>(&id("genome-fasta-list")).unwrap();
+                let mut fasta_paths = read_genome_fasta_list_file(Path::new(file_path))?;
+                if options.list_paths_relative_to_list
+                    || flag_is_set(m, &id("genome-fasta-list-relative-to-list"))
+                {
+                    fasta_paths = resolve_genome_fasta_list_paths(fasta_paths, Path::new(file_path));
+                }
+                if fasta_paths.is_empty() && fail_on_no_genomes {
+                    return Err(GenomeSpecificationError::EmptyListFile {
+                        path: PathBuf::from(file_path),
+                    });
+                }
+                Ok(fasta_paths)
+            } else if m.contains_id(&id("single-genome-fasta")) {
+                let path = m.get_one::<String>(&id("single-genome-fasta")).unwrap();
+                Ok(vec![GenomeFastaFile::from_path(PathBuf::from(path))])
             } else {
-                return std::result::Result::Err(
-                    "No genome specification options specified".to_string(),
+                std::result::Result::Err(GenomeSpecificationError::NoSpecification)
+            }
+        }
+    }
+}
+
+/// The genome specification argument used, e.g. '--genome-fasta-directory',
+/// for describing where genomes were looked for.
+fn genome_specification_argument(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+) -> String {
+    GENOME_SPECIFICATION_ARGUMENTS
+        .iter()
+        .map(|id| options.argument_id(id))
+        .find(|id| m.contains_id(id))
+        .map(|id| format!("--{}", id))
+        .unwrap_or_else(|| "no genome specification".to_string())
+}
+
+/// The extensions given with --genome-fasta-extension, which may be a
+/// comma-separated list.
+pub(crate) fn genome_fasta_extension_values(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+) -> Vec<String> {
+    m.get_one::<String>(&options.argument_id("genome-fasta-extension"))
+        .unwrap()
+        .split(',')
+        .map(|e| e.to_string())
+        .collect()
+}
+
+/// The maximum number of genomes, from --max-genomes if it was given and
+/// otherwise from options.
+fn max_genomes(m: &clap::ArgMatches, options: &GenomeSpecificationOptions) -> Option<usize> {
+    match m.try_get_one::<usize>(&options.argument_id("max-genomes")) {
+        Ok(Some(max)) => Some(*max),
+        _ => options.max_genomes,
+    }
+}
+
+/// Warn when genome FASTA files found in different directories have the same
+/// file name, since downstream tools often name genomes by their file name.
+fn warn_about_duplicate_file_names(paths: &[PathBuf]) {
+    let mut name_to_paths: BTreeMap<String, Vec<&PathBuf>> = BTreeMap::new();
+    for path in paths {
+        if let Some(name) = path.file_name() {
+            name_to_paths
+                .entry(name.to_string_lossy().to_string())
+                .or_default()
+                .push(path);
+        }
+    }
+    for (name, paths) in name_to_paths {
+        if paths.len() > 1 {
+            warn!(
+                "Genome FASTA file name '{}' was found in multiple directories: {}",
+                name,
+                paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+}
+
+/// Prefix of --genome-fasta-files values which name a response file.
+const RESPONSE_FILE_PREFIX: char = '@';
+
+/// The values given to --genome-fasta-files, with each value of the form
+/// '@path' replaced by the lines of the response file path. Blank lines are
+/// ignored, and lines are used as is, i.e. response files are not expanded
+/// within response files. A value starting with '@@' is used literally, with
+/// the first '@' removed.
+pub(crate) fn genome_fasta_files_values(
+    m: &clap::ArgMatches,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<String>, GenomeSpecificationError> {
+    let mut values = vec![];
+    let files = options.argument_id("genome-fasta-files");
+    for value in m.get_many::<String>(&files).unwrap() {
+        match value.strip_prefix(RESPONSE_FILE_PREFIX) {
+            Some(literal) if literal.starts_with(RESPONSE_FILE_PREFIX) => {
+                values.push(literal.to_string())
+            }
+            Some(response_file) => {
+                let path = PathBuf::from(response_file);
+                let contents = std::fs::read_to_string(&path).map_err(|e| {
+                    GenomeSpecificationError::UnreadableResponseFile {
+                        path: path.clone(),
+                        source: e,
+                    }
+                })?;
+                let num_values = values.len();
+                values.extend(
+                    contents
+                        .lines()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty())
+                        .map(|line| line.to_string()),
+                );
+                debug!(
+                    "Read {} genome FASTA file(s) from response file '{}'",
+                    values.len() - num_values,
+                    path.display()
                 );
             }
+            None => values.push(value.clone()),
+        }
+    }
+    Ok(values)
+}
+
+/// Whether a value contains glob metacharacters, i.e. it was not expanded by
+/// the shell (e.g. it was quoted, or passed by a workflow manager).
+pub(crate) fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Expand a glob pattern given to --genome-fasta-files into a sorted list of
+/// paths, returning an Err if the pattern is invalid or matches nothing.
+pub(crate) fn expand_genome_fasta_glob(
+    pattern: &str,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    let paths = glob::glob(pattern).map_err(|e| GenomeSpecificationError::InvalidGlobPattern {
+        pattern: pattern.to_string(),
+        message: e.to_string(),
+    })?;
+    let mut expanded = vec![];
+    for path in paths {
+        match path {
+            Ok(path) => expanded.push(path),
+            Err(e) => warn!("Error while expanding glob pattern '{}': {}", pattern, e),
+        }
+    }
+    if expanded.is_empty() {
+        return Err(GenomeSpecificationError::UnmatchedGlobPattern {
+            pattern: pattern.to_string(),
+        });
+    }
+    expanded.sort();
+    debug!("Expanded pattern '{}' into {} path(s)", pattern, expanded.len());
+    Ok(expanded)
+}
+
+/// Whether a boolean flag was given, treating flags that were not defined on
+/// the command as unset.
+pub(crate) fn flag_is_set(m: &clap::ArgMatches, id: &str) -> bool {
+    matches!(m.try_get_one::<bool>(id), Ok(Some(true)))
+}
+
+/// Name of the clap ArgGroup containing the genome specification arguments,
+/// only one of which can be given. It is required when added by
+/// add_genome_specification_arguments_required().
+pub const GENOME_SPECIFICATION_GROUP: &str = "genome-specification";
+
+/// Arguments in the GENOME_SPECIFICATION_GROUP, only one of which can be used.
+const GENOME_SPECIFICATION_ARGUMENTS: &[&str] = &[
+    "genome-fasta-files",
+    "genome-fasta-directory",
+    "genome-fasta-list",
+    "genome-fasta-archive",
+    "single-genome-fasta",
+];
+
+/// Description of one of the arguments added by
+/// add_genome_specification_arguments(), from which both its clap Arg and its
+/// entry in the man page section made by add_genome_specification_to_section()
+/// are generated, so that the two cannot drift apart.
+pub struct GenomeSpecOption {
+    /// clap argument id, which is also the long flag.
+    pub name: &'static str,
+    pub short: Option<char>,
+    /// Name of the value in clap's help and the man page e.g. 'PATH', or None
+    /// for flags which take no value.
+    pub value_name: Option<&'static str>,
+    /// Kind of value, for shell completion.
+    pub value_hint: clap::ValueHint,
+    /// Help shown by clap's --help.
+    pub help: &'static str,
+    /// Longer help for the man page, formatted with roff.
+    pub man_help: fn() -> String,
+    pub default: Option<&'static str>,
+    pub action: clap::ArgAction,
+    /// Accept any number of values after the flag, e.g. '-f a.fna b.fna'.
+    pub multiple_values: bool,
+    pub requires: Option<&'static str>,
+    /// Parser for the value, e.g. to only accept numbers, rather than taking
+    /// any string.
+    pub value_parser: Option<fn() -> clap::builder::ValueParser>,
+    /// Leave out of --help, as for arguments named in
+    /// GenomeSpecArgsConfig::hidden.
+    pub hidden: bool,
+}
+
+/// The arguments added by add_genome_specification_arguments(), in the order
+/// they are documented.
+pub const GENOME_SPEC_OPTIONS: &[GenomeSpecOption] = &[
+    GenomeSpecOption {
+        name: "genome-fasta-files",
+        short: Some('f'),
+        value_name: Some("PATH"),
+        value_hint: clap::ValueHint::FilePath,
+        help: "List of fasta files for processing. Values of the form @FILE are replaced by the paths listed one per line in FILE",
+        man_help: || {
+            format!(
+                "Path(s) to FASTA files of each genome e.g. {}. \
+                Glob patterns which have not been expanded by the shell \
+                e.g. {} are expanded. A value of the form {} is replaced \
+                by the paths listed one per line in FILE, which avoids \
+                command lines becoming too long. Paths which start with \
+                '@' can be given by doubling it e.g. {}.",
+                monospace_roff("pathA/genome1.fna pathB/genome2.fa"),
+                monospace_roff("'bins/*.fna'"),
+                monospace_roff("@FILE"),
+                monospace_roff("@@genome.fna")
+            )
+        },
+        default: None,
+        action: clap::ArgAction::Append,
+        multiple_values: true,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-directory",
+        short: Some('d'),
+        value_name: Some("DIR"),
+        value_hint: clap::ValueHint::DirPath,
+        help: "Directory containing fasta files for processing. May be specified multiple times",
+        man_help: || {
+            "Directory containing FASTA files of each genome. Can be \
+            specified multiple times to use genomes from several \
+            directories. Symlinks are followed, but it is the name of \
+            the link rather than of its target which must end with the \
+            genome FASTA extension. Hidden files, whose names start \
+            with '.' (e.g. '._genome.fna' files made by macOS), are \
+            not used."
+                .to_string()
+        },
+        default: None,
+        action: clap::ArgAction::Append,
+        multiple_values: false,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-directory-recursive",
+        short: None,
+        value_name: None,
+        value_hint: clap::ValueHint::Unknown,
+        help: "Also search subdirectories of --genome-fasta-directory",
+        man_help: || {
+            format!(
+                "Also search subdirectories of the directory specified with {}. \
+                Symlinked directories are followed, but each directory is only \
+                searched once. Hidden subdirectories, whose names start with \
+                '.' (e.g. '.snakemake'), are not searched.",
+                monospace_roff("-d/--genome-fasta-directory")
+            )
+        },
+        default: None,
+        action: clap::ArgAction::SetTrue,
+        multiple_values: false,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "single-genome-fasta",
+        short: None,
+        value_name: Some("PATH"),
+        value_hint: clap::ValueHint::FilePath,
+        help: "Single fasta file for processing, for tools which use only one genome",
+        man_help: || {
+            "Path to the FASTA file of a single genome, for use where \
+            only one genome is expected."
+                .to_string()
+        },
+        default: None,
+        action: clap::ArgAction::Set,
+        multiple_values: false,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-archive",
+        short: None,
+        value_name: Some("PATH"),
+        value_hint: clap::ValueHint::FilePath,
+        help: "tar or tar.gz archive containing fasta files for processing. May be specified multiple times",
+        man_help: || {
+            format!(
+                "tar archive, optionally gzip compressed, containing FASTA \
+                files of each genome. Members with the genome FASTA \
+                extension (see {}) are extracted to a temporary directory \
+                and used. Can be specified multiple times.",
+                monospace_roff("-x/--genome-fasta-extension")
+            )
+        },
+        default: None,
+        action: clap::ArgAction::Append,
+        multiple_values: false,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-extension",
+        short: Some('x'),
+        value_name: Some("EXT"),
+        value_hint: clap::ValueHint::Unknown,
+        help: "File extension of FASTA files in --genome-fasta-directory or --genome-fasta-archive. Multiple extensions may be given separated by commas e.g. 'fa,fna,fasta', or 'auto' to detect the extension",
+        man_help: || {
+            format!(
+                "File extension of genomes in the directory \
+                specified with {} or archive specified with {}. Several extensions can be given as a \
+                comma-separated list e.g. {}, in which case files ending \
+                in any of them are used. Compressed genomes can be \
+                specified with a compound extension e.g. {}. Matching is \
+                case-insensitive. Specify {} to use whichever of {} are \
+                present.",
+                monospace_roff("-d/--genome-fasta-directory"),
+                monospace_roff("--genome-fasta-archive"),
+                monospace_roff("fa,fna,fasta"),
+                monospace_roff("fna.gz"),
+                monospace_roff("auto"),
+                monospace_roff(&AUTO_DETECTED_EXTENSIONS.join(","))
+            )
+        },
+        default: Some("fna"),
+        action: clap::ArgAction::Set,
+        multiple_values: false,
+        // Unsure why, but requiring genome-fasta-directory causes test
+        // failure (in coverm genome mode where this code was pasted from, not
+        // sure about here) - clap bug?
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-list",
+        short: None,
+        value_name: Some("PATH"),
+        value_hint: clap::ValueHint::FilePath,
+        help: "List of fasta file paths, one per line, for processing. Use '-' to read from stdin",
+        man_help: || {
+            "File containing FASTA file paths, one per line, which may \
+            be gzip compressed. Blank lines and lines starting with '#' \
+            are ignored. Specify '-' to read the list from stdin. A line \
+            may optionally contain a tab followed by a name for the \
+            genome, which is used instead of a name derived from the \
+            file name."
+                .to_string()
+        },
+        default: None,
+        action: clap::ArgAction::Set,
+        multiple_values: false,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-list-relative-to-list",
+        short: None,
+        value_name: None,
+        value_hint: clap::ValueHint::Unknown,
+        help: "Resolve relative paths in --genome-fasta-list against the directory containing the list",
+        man_help: || {
+            format!(
+                "Resolve relative paths in the list given with {} against \
+                the directory containing the list, rather than the current \
+                working directory.",
+                monospace_roff("--genome-fasta-list")
+            )
+        },
+        default: None,
+        action: clap::ArgAction::SetTrue,
+        multiple_values: false,
+        requires: Some("genome-fasta-list"),
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "genome-fasta-exclude",
+        short: None,
+        value_name: Some("PATTERN"),
+        value_hint: clap::ValueHint::Unknown,
+        help: "Do not use genome FASTA files whose file name matches this glob pattern or contains this text. May be specified multiple times",
+        man_help: || {
+            format!(
+                "Do not use genome FASTA files whose file name matches this \
+                glob pattern (e.g. {}) or, if it contains no glob \
+                characters, contains this text. Applies to genomes \
+                specified in any way. Can be specified multiple times.",
+                monospace_roff("'bin.1*.fna'")
+            )
+        },
+        default: None,
+        action: clap::ArgAction::Append,
+        multiple_values: false,
+        requires: None,
+        value_parser: None,
+        hidden: false,
+    },
+    GenomeSpecOption {
+        name: "max-genomes",
+        short: None,
+        value_name: Some("N"),
+        value_hint: clap::ValueHint::Unknown,
+        help: "Fail if more than this many genomes are specified",
+        man_help: || {
+            "Fail if more than this many genomes are specified, rather \
+            than spending time checking them all."
+                .to_string()
+        },
+        default: None,
+        action: clap::ArgAction::Set,
+        multiple_values: false,
+        requires: None,
+        value_parser: Some(|| clap::value_parser!(usize).into()),
+        hidden: true,
+    },
+];
+
+impl GenomeSpecOption {
+    /// The clap argument for this option.
+    pub fn to_arg(&self) -> Arg {
+        self.to_prefixed_arg(GENOME_ARGUMENT_PREFIX)
+    }
+
+    /// The clap argument for this option, with 'genome' in its id, long flag
+    /// and any arguments it refers to replaced by prefix (see
+    /// prefixed_argument_id()). The short flag is only used for the genome
+    /// prefix, so that arguments with different prefixes do not collide.
+    pub fn to_prefixed_arg(&self, prefix: &str) -> Arg {
+        let id = prefixed_argument_id(self.name, prefix);
+        let mut arg = Arg::new(id.clone())
+            .long(id)
+            .help(self.help.replace(
+                &format!("{}-fasta", GENOME_ARGUMENT_PREFIX),
+                &format!("{}-fasta", prefix),
+            ))
+            .action(self.action.clone());
+        if let Some(value_name) = self.value_name {
+            arg = arg.value_name(value_name).value_hint(self.value_hint);
+        }
+        if let Some(short) = self.short {
+            if prefix == GENOME_ARGUMENT_PREFIX {
+                arg = arg.short(short);
+            }
+        }
+        if let Some(default) = self.default {
+            arg = arg.default_value(default);
+        }
+        if self.multiple_values {
+            arg = arg.num_args(0..);
+        }
+        if let Some(requires) = self.requires {
+            arg = arg.requires(prefixed_argument_id(requires, prefix));
+        }
+        if let Some(value_parser) = self.value_parser {
+            arg = arg.value_parser(value_parser());
+        }
+        arg.hide(self.hidden)
+    }
+
+    /// Add the man page entry for this option to section.
+    pub fn add_to_section(&self, section: Section) -> Section {
+        self.add_to_section_with_config(section, &GenomeSpecArgsConfig::default())
+    }
+
+    /// Add the man page entry for this option to section, noting if the
+    /// option is hidden from --help by config.
+    pub fn add_to_section_with_config(
+        &self,
+        section: Section,
+        config: &GenomeSpecArgsConfig,
+    ) -> Section {
+        let short = self.short.map(|c| format!("-{}", c));
+        let long = format!("--{}", self.name);
+        let mut help = match config.help_for(self.name) {
+            Some(help) => help.to_string(),
+            None => (self.man_help)(),
+        };
+        if let Some(default) = config.default_for(self) {
+            help = format!("{} {}", help, default_roff(&default));
+        }
+        if self.hidden || config.hides(self.name) {
+            help = format!("{} {}", help, HIDDEN_OPTION_NOTE);
+        }
+        match self.value_name {
+            Some(value_name) => {
+                let value_name = match self.multiple_values {
+                    true => format!("{} ..", value_name),
+                    false => value_name.to_string(),
+                };
+                let mut opt = Opt::new(&value_name).long(&long).help(&help);
+                if let Some(short) = &short {
+                    opt = opt.short(short);
+                }
+                section.option(opt)
+            }
+            None => {
+                let mut flag = Flag::new().long(&long).help(&help);
+                if let Some(short) = &short {
+                    flag = flag.short(short);
+                }
+                section.flag(flag)
+            }
+        }
+    }
+}
+
+/// Customisation of the genome arguments, for use with
+/// add_genome_specification_arguments_with_config() and
+/// add_genome_specification_to_section_with_config().
+#[derive(Debug, Clone, Default)]
+pub struct GenomeSpecArgsConfig {
+    /// Require one of the genome specification arguments, as
+    /// add_genome_specification_arguments_required() does.
+    pub required: bool,
+    /// Names of arguments, e.g. "genome-fasta-extension", to leave out of
+    /// --help because they are rarely used. They are still documented in the
+    /// man page, e.g. as shown by print_full_help_if_needed().
+    pub hidden: Vec<String>,
+    /// Default for --genome-fasta-extension, instead of 'fna'.
+    pub default_extension: Option<String>,
+    /// Help for --genome-fasta-files, used in both --help and the man page.
+    pub files_help: Option<String>,
+    /// Help for --genome-fasta-directory.
+    pub directory_help: Option<String>,
+    /// Help for --genome-fasta-list.
+    pub list_help: Option<String>,
+    /// Help for --genome-fasta-extension.
+    pub extension_help: Option<String>,
+}
+
+impl GenomeSpecArgsConfig {
+    /// Whether the argument name (as in GENOME_SPEC_OPTIONS) is hidden.
+    pub fn hides(&self, name: &str) -> bool {
+        self.hidden.iter().any(|h| h == name)
+    }
+
+    /// Help for the argument name replacing that in GENOME_SPEC_OPTIONS, if
+    /// any.
+    pub fn help_for(&self, name: &str) -> Option<&str> {
+        match name {
+            "genome-fasta-files" => self.files_help.as_deref(),
+            "genome-fasta-directory" => self.directory_help.as_deref(),
+            "genome-fasta-list" => self.list_help.as_deref(),
+            "genome-fasta-extension" => self.extension_help.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Default value of option, taking default_extension into account.
+    pub fn default_for(&self, option: &GenomeSpecOption) -> Option<String> {
+        match (option.name, &self.default_extension) {
+            ("genome-fasta-extension", Some(extension)) => Some(extension.clone()),
+            _ => option.default.map(|d| d.to_string()),
         }
     }
 }
 
 /// Add --genome-fasta-files and --genome-fasta-directory etc. to a clap App /
 /// subcommand. These arguments can later be parsed with
-/// parse_list_of_genome_fasta_files().
-pub fn add_genome_specification_arguments<'a>(subcommand: clap::Command) -> clap::Command {
-    subcommand
-        .arg(
-            Arg::new("genome-fasta-files")
-                .short('f')
-                .long("genome-fasta-files")
-                .help("List of fasta files for processing")
-                .conflicts_with_all(&["genome-fasta-directory", "genome-fasta-list"])
-                .action(clap::ArgAction::Append)
-                .num_args(0..)
-        )
-        .arg(
-            Arg::new("genome-fasta-list")
-                .long("genome-fasta-list")
-                .help("List of fasta file paths, one per line, for processing")
-                .conflicts_with("genome-fasta-directory")
+/// parse_list_of_genome_fasta_files(). The arguments are described by
+/// GENOME_SPEC_OPTIONS.
+pub fn add_genome_specification_arguments(subcommand: clap::Command) -> clap::Command {
+    add_fasta_specification_arguments(subcommand, GENOME_ARGUMENT_PREFIX)
+}
+
+/// As add_genome_specification_arguments(), except that 'genome' in the name
+/// of each argument is replaced by prefix, e.g. --reference-fasta-files for
+/// the prefix 'reference', so that more than one set of FASTA files can be
+/// specified to the same subcommand. Short flags such as -f are only added for
+/// the 'genome' prefix. These arguments can later be parsed with
+/// parse_list_of_fasta_files().
+pub fn add_fasta_specification_arguments(
+    subcommand: clap::Command,
+    prefix: &str,
+) -> clap::Command {
+    add_fasta_specification_arguments_with_config(
+        subcommand,
+        prefix,
+        &GenomeSpecArgsConfig::default(),
+    )
+}
+
+/// As add_genome_specification_arguments(), except that clap reports a usage
+/// error if none of the genome specification arguments are given, rather than
+/// this being left to parse_list_of_genome_fasta_files().
+pub fn add_genome_specification_arguments_required(subcommand: clap::Command) -> clap::Command {
+    add_genome_specification_arguments_with_config(
+        subcommand,
+        &GenomeSpecArgsConfig {
+            required: true,
+            ..Default::default()
+        },
+    )
+}
+
+/// As add_genome_specification_arguments(), customised by config.
+pub fn add_genome_specification_arguments_with_config(
+    subcommand: clap::Command,
+    config: &GenomeSpecArgsConfig,
+) -> clap::Command {
+    add_fasta_specification_arguments_with_config(subcommand, GENOME_ARGUMENT_PREFIX, config)
+}
+
+/// As add_fasta_specification_arguments(), customised by config. The
+/// arguments are added along with a group of the genome specification
+/// arguments so that clap allows only one of them.
+pub fn add_fasta_specification_arguments_with_config(
+    subcommand: clap::Command,
+    prefix: &str,
+    config: &GenomeSpecArgsConfig,
+) -> clap::Command {
+    GENOME_SPEC_OPTIONS
+        .iter()
+        .fold(subcommand, |subcommand, option| {
+            let mut arg = option
+                .to_prefixed_arg(prefix)
+                .hide(option.hidden || config.hides(option.name));
+            if let Some(help) = config.help_for(option.name) {
+                arg = arg.help(help.to_string());
+            }
+            if let Some(default) = config.default_for(option) {
+                arg = arg.default_value(default);
+            }
+            subcommand.arg(arg)
+        })
+        .group(
+            ArgGroup::new(prefixed_argument_id(GENOME_SPECIFICATION_GROUP, prefix))
+                .args(
+                    GENOME_SPECIFICATION_ARGUMENTS
+                        .iter()
+                        .map(|id| prefixed_argument_id(id, prefix)),
+                )
+                .multiple(false)
+                .required(config.required),
         )
-        .arg(
-            Arg::new("genome-fasta-directory")
-                .long("genome-fasta-directory")
-                .help("Directory containing fasta files for processing")
+}
+
+/// Make a Manual for a (sub)command of a tool, with the name, version,
+/// authors and homepage of the calling crate, and the flags and options added
+/// by add_clap_verbosity_flags() along with --full-help and --full-help-roff.
+/// Only the options and examples specific to the command then need adding. The
+/// subcommand can be empty for tools without subcommands. For example
+/// new_manual_skeleton!("genome", "Calculate coverage of genomes") makes a
+/// manual named 'coverm-genome' for CoverM. See manual_skeleton().
+#[macro_export]
+macro_rules! new_manual_skeleton {
+    ($subcommand:expr, $about:expr) => {
+        $crate::clap_utils::manual_skeleton(
+            env!("CARGO_PKG_NAME"),
+            $subcommand,
+            $about,
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_AUTHORS"),
+            env!("CARGO_PKG_HOMEPAGE"),
         )
-        .arg(
-            Arg::new("genome-fasta-extension")
-                .short('x')
-                .help("File extension of FASTA files in --genome-fasta-directory")
-                .long("genome-fasta-extension")
-                // Unsure why, but uncommenting causes test failure (in
-                // coverm genome mode where this code was pasted from,
-                // not sure about here) - clap bug?
-                //.requires("genome-fasta-directory")
-                .default_value("fna")
+    };
+}
+
+/// Make a Manual as for new_manual_skeleton!(), from package metadata. authors
+/// are separated by ':' and may include email addresses in angle brackets,
+/// as in CARGO_PKG_AUTHORS. The version and homepage are given in the
+/// description, which can be replaced if needed.
+pub fn manual_skeleton(
+    package: &str,
+    subcommand: &str,
+    about: &str,
+    version: &str,
+    authors: &str,
+    homepage: &str,
+) -> Manual {
+    let name = match subcommand.is_empty() {
+        true => package.to_string(),
+        false => format!("{}-{}", package, subcommand),
+    };
+    let mut description = format!("{} version {}.", escape_roff(package), escape_roff(version));
+    if !homepage.is_empty() {
+        description.push_str(&format!(" Homepage:{}", url_roff(homepage, None)));
+    }
+    let mut manual = Manual::new(&name).about(about).description(description);
+    for author in authors.split(':').map(|author| author.trim()) {
+        if author.is_empty() {
+            continue;
+        }
+        manual = manual.author(match author.split_once('<') {
+            Some((name, email)) => {
+                bird_tool_utils_man::prelude::Author::new(name.trim())
+                    .email(email.trim_end_matches('>').trim())
+            }
+            None => bird_tool_utils_man::prelude::Author::new(author),
+        });
+    }
+    manual
+        .flag(
+            Flag::new()
+                .short("-v")
+                .long("--verbose")
+                .help("Print extra debug logging information. Specify twice for trace logging"),
         )
+        .flag(Flag::new().long("--quiet").help(
+            "Unless there is an error, do not print logging information. Specify twice to not print errors either",
+        ))
+        .option(Opt::new("LEVEL").long("--log-level").help(&format!(
+            "Print logging information at this level and above, instead of using --verbose or --quiet. One of {}.",
+            monospace_roff("off, error, warn, info, debug, trace")
+        )))
+        .flag(Flag::new().long("--full-help").help("Output a full help message and display in 'man'"))
+        .flag(Flag::new().long("--full-help-roff").help(
+            "Output a full help message in raw ROFF format for conversion to other formats",
+        ))
 }
 
+/// Document the arguments added by add_genome_specification_arguments() in a
+/// man page section.
 pub fn add_genome_specification_to_section(section: Section) -> Section {
-    section
-        .option(
-            Opt::new("PATH ..")
-                .short("-f")
-                .long("--genome-fasta-files")
-                .help(&format!(
-                    "Path(s) to FASTA files of each genome e.g. {}.",
-                    monospace_roff("pathA/genome1.fna pathB/genome2.fa")
-                )),
-        )
-        .option(
-            Opt::new("PATH")
-                .short("-d")
-                .long("--genome-fasta-directory")
-                .help("Directory containing FASTA files of each genome."),
-        )
-        .option(
-            Opt::new("EXT")
-                .short("-x")
-                .long("--genome-fasta-extension")
-                .help(&format!(
-                    "File extension of genomes in the directory \
-                specified with {}. {}",
-                    monospace_roff("-d/--genome-fasta-directory"),
-                    default_roff("fna")
-                )),
-        )
-        .option(
-            Opt::new("PATH")
-                .long("--genome-fasta-list")
-                .help("File containing FASTA file paths, one per line."),
-        )
+    add_genome_specification_to_section_with_config(section, &GenomeSpecArgsConfig::default())
+}
+
+/// As add_genome_specification_to_section(), for arguments added by
+/// add_genome_specification_arguments_with_config() with config.
+pub fn add_genome_specification_to_section_with_config(
+    section: Section,
+    config: &GenomeSpecArgsConfig,
+) -> Section {
+    GENOME_SPEC_OPTIONS.iter().fold(section, |section, option| {
+        option.add_to_section_with_config(section, config)
+    })
+}
+
+/// Flag or option of a clap::Command, as documented by opts_from_command().
+#[derive(Debug, Clone)]
+pub enum ManualArg {
+    Flag(Flag),
+    Option(Opt),
+}
+
+impl ManualArg {
+    pub fn add_to_section(self, section: Section) -> Section {
+        match self {
+            ManualArg::Flag(flag) => section.flag(flag),
+            ManualArg::Option(opt) => section.option(opt),
+        }
+    }
+}
+
+/// Document the arguments of cmd for a man page, so that the help written for
+/// --help does not need to be repeated. The long help of each argument is
+/// used if it has one, escaped with escape_roff(), followed by its default
+/// value as shown by default_roff() or default_list_roff(). Flags and options
+/// are returned in the order they were defined, and can be added to a Section
+/// with ManualArg::add_to_section(). Positional arguments are returned
+/// separately, as an Opt with no flag. Hidden arguments are only included if
+/// include_hidden. The --help and --version flags clap adds are not included.
+pub fn opts_from_command(
+    cmd: &clap::Command,
+    include_hidden: bool,
+) -> (Vec<ManualArg>, Vec<Opt>) {
+    let mut args = vec![];
+    let mut positionals = vec![];
+    for arg in cmd.get_arguments() {
+        if arg.is_hide_set() && !include_hidden {
+            continue;
+        }
+        let mut help = match arg.get_long_help().or_else(|| arg.get_help()) {
+            Some(help) => escape_roff(&help.to_string()),
+            None => String::new(),
+        };
+        let takes_values = arg.get_action().takes_values();
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| value.to_string_lossy().to_string())
+            .collect();
+        if takes_values && !defaults.is_empty() {
+            let defaults: Vec<&str> = defaults.iter().map(|value| value.as_str()).collect();
+            let default = match defaults.as_slice() {
+                [default] => default_roff(default),
+                defaults => default_list_roff(defaults),
+            };
+            help = format!("{} {}", help, default).trim_start().to_string();
+        }
+        if arg.is_hide_set() {
+            if !help.is_empty() && !help.ends_with('.') {
+                help.push('.');
+            }
+            help = format!("{} {}", help, HIDDEN_OPTION_NOTE).trim_start().to_string();
+        }
+        let value_name = match arg.get_value_names() {
+            Some(names) => names.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(" "),
+            None => arg.get_id().as_str().to_uppercase(),
+        };
+        let multiple_values = arg.get_num_args().is_some_and(|range| range.max_values() > 1);
+        let value_name = match multiple_values {
+            true => format!("{} ..", value_name),
+            false => value_name,
+        };
+        if arg.is_positional() {
+            positionals.push(Opt::new(&value_name).help(&help));
+            continue;
+        }
+        let short = arg.get_short().map(|c| format!("-{}", c));
+        let long = arg.get_long().map(|long| format!("--{}", long));
+        args.push(match takes_values {
+            true => {
+                let mut opt = Opt::new(&value_name).help(&help);
+                if let Some(short) = &short {
+                    opt = opt.short(short);
+                }
+                if let Some(long) = &long {
+                    opt = opt.long(long);
+                }
+                ManualArg::Option(opt)
+            }
+            false => {
+                let mut flag = Flag::new().help(&help);
+                if let Some(short) = &short {
+                    flag = flag.short(short);
+                }
+                if let Some(long) = &long {
+                    flag = flag.long(long);
+                }
+                ManualArg::Flag(flag)
+            }
+        });
+    }
+    (args, positionals)
 }
 
+/// Add --verbose, --quiet and --log-level flags, which are handled by
+/// set_log_level(). --verbose and --quiet can be given more than once, so they
+/// are counted, and their values should be read with get_count() rather than
+/// get_flag(), which panics for them. Tools which used get_flag("verbose") with
+/// earlier versions of this crate, when it was a boolean flag, need to change
+/// to get_count("verbose") > 0.
 pub fn add_clap_verbosity_flags(cmd: clap::Command) -> clap::Command {
     cmd
     .args(&[
-        arg!(-v --verbose "Print extra debug logging information"),
-        arg!(--quiet "Unless there is an error, do not print logging information"),
+        Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .help("Print extra debug logging information. Specify twice for trace logging")
+            .action(clap::ArgAction::Count),
+        Arg::new("quiet")
+            .long("quiet")
+            .help("Unless there is an error, do not print logging information. Specify twice to not print errors either")
+            .conflicts_with("verbose")
+            .action(clap::ArgAction::Count),
+        Arg::new("log-level")
+            .long("log-level")
+            .value_name("LEVEL")
+            .help("Print logging information at this level and above, instead of using --verbose or --quiet")
+            .value_parser(["off", "error", "warn", "info", "debug", "trace"])
+            .conflicts_with_all(["verbose", "quiet"]),
+    ])
+}
+
+/// Add the flags of add_clap_verbosity_flags(), along with --log-file to
+/// write log output to a file as well as stderr, --log-file-append,
+/// --log-format, --log-threads, --log-elapsed, and --color and --no-color.
+/// These are all handled by set_log_level().
+pub fn add_clap_logging_flags(cmd: clap::Command) -> clap::Command {
+    add_clap_verbosity_flags(cmd).args(&[
+        Arg::new("log-file")
+            .long("log-file")
+            .value_name("PATH")
+            .value_hint(clap::ValueHint::FilePath)
+            .help("Also write logging information to this file"),
+        Arg::new("log-file-append")
+            .long("log-file-append")
+            .help("Append to --log-file rather than overwriting it")
+            .requires("log-file")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .help("Format of logging information: 'text', or 'json' for one JSON object per line")
+            .value_parser(["text", "json"]),
+        Arg::new("log-threads")
+            .long("log-threads")
+            .help("Include the name of the thread in logging information")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("log-elapsed")
+            .long("log-elapsed")
+            .help("Start each line of logging information with the time since the program started")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("color")
+            .long("color")
+            .value_name("WHEN")
+            .help("Colour logging information: 'auto' to colour only when writing to a terminal and the NO_COLOR environment variable is unset, 'always' e.g. for viewing with 'less -R', or 'never'")
+            .value_parser(["auto", "always", "never"]),
+        Arg::new("no-color")
+            .long("no-color")
+            .help("Do not colour logging information, as for --color never")
+            .conflicts_with("color")
+            .action(clap::ArgAction::SetTrue),
     ])
 }
 
+/// Display the manual with 'man' and exit, or if it cannot be displayed,
+/// print the problem and exit with status 1. See display_full_help_noexit().
 pub fn display_full_help(manual: Manual) {
-    let mut f =
-        tempfile::NamedTempFile::new().expect("Failed to create temporary file for --full-help");
-    write!(f, "{}", manual.render()).expect("Failed to write to tempfile for full-help");
-    let child = std::process::Command::new("man")
-        .args(&[f.path()])
-        .spawn()
-        .expect("Failed to spawn 'man' command for --full-help");
+    if let Err(e) = display_full_help_noexit(manual) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    process::exit(0);
+}
 
-    crate::command::finish_command_safely(child, &"man");
-    std::process::exit(0);
+/// Display the manual with 'man', returning once it has been closed. The
+/// output of 'man -l' is piped to $MANPAGER, or if that is unset or fails,
+/// $PAGER e.g. 'bat -l man', as when man pages are shown by 'man' itself. If
+/// neither works, 'man' is run directly.
+/// If stdout is not a terminal, e.g. when redirected to a file, the manual is
+/// instead printed as plain text (see render_manual_plaintext()) wrapped to
+/// $COLUMNS or 100 columns. If 'man' is not installed, as in many minimal Docker
+/// images, or fails, the plain text is shown with $MANPAGER or $PAGER, or if
+/// neither is set or works, printed to stdout.
+/// The temporary file given to 'man' is removed once the pager has exited. An Err is
+/// returned if the temporary file cannot be written, or the manual cannot be
+/// printed.
+pub fn display_full_help_noexit(manual: Manual) -> std::result::Result<(), String> {
+    display_full_help_roff(&manual.render())
 }
 
-pub fn default_roff(s: &str) -> String {
-    format!("[default: \\f[C]{}\\f[R]]", s)
+/// As display_full_help_noexit(), but for a manual already rendered to roff.
+fn display_full_help_roff(roff: &str) -> std::result::Result<(), String> {
+    if !std::io::stdout().is_terminal() {
+        debug!("Printing --full-help as plain text as stdout is not a terminal");
+        return print_full_help_text(&plain_text_from_roff(roff, full_help_width()));
+    }
+    display_full_help_roff_with_env(roff, &mut std::io::stdout(), &FullHelpEnv::from_env())
 }
 
-pub fn monospace_roff(s: &str) -> String {
-    format!("\\f[C]{}\\f[R]", s)
+/// The environment 'man' and pagers are run in to display --full-help.
+#[derive(Debug, Clone, Default)]
+struct FullHelpEnv {
+    /// PATH to look for 'man' and pagers in, or None to use that of the
+    /// process.
+    path: Option<std::ffi::OsString>,
+    /// Pagers from $MANPAGER and $PAGER, in that order, with the variable
+    /// each is from. Empty values are left out.
+    pagers: Vec<(&'static str, String)>,
 }
 
-pub fn list_roff(strings: &[&str]) -> String {
-    let mut s: String = "\n".to_string(); //start with a new line so the first .IP starts at the first char of the row
-    for e in strings {
-        s.push_str(".IP \\[bu] 2\n");
+impl FullHelpEnv {
+    fn from_env() -> FullHelpEnv {
+        FullHelpEnv {
+            path: None,
+            pagers: ["MANPAGER", "PAGER"]
+                .iter()
+                .filter_map(|var| match env::var(var) {
+                    Ok(pager) if !pager.trim().is_empty() => Some((*var, pager)),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    fn command(&self, program: &str) -> std::process::Command {
+        let mut command = std::process::Command::new(program);
+        if let Some(path) = &self.path {
+            command.env("PATH", path);
+        }
+        command
+    }
+}
+
+/// As display_full_help_roff() when stdout is a terminal, running 'man' and
+/// pagers in full_help_env, and printing to out if neither works.
+fn display_full_help_roff_with_env(
+    roff: &str,
+    out: &mut dyn Write,
+    full_help_env: &FullHelpEnv,
+) -> std::result::Result<(), String> {
+    if !display_roff_with_man(roff, full_help_env)? {
+        let text = plain_text_from_roff(roff, full_help_width());
+        if !display_with_pager(&text, full_help_env) {
+            return write_full_help_text(out, &text);
+        }
+    }
+    Ok(())
+}
+
+/// Write roff to a temporary file and show it with display_with_man(),
+/// returning whether it was shown. The temporary file is removed afterwards.
+fn display_roff_with_man(
+    roff: &str,
+    full_help_env: &FullHelpEnv,
+) -> std::result::Result<bool, String> {
+    let mut f = tempfile::NamedTempFile::new()
+        .map_err(|e| format!("Failed to create temporary file for --full-help: {}", e))?;
+    f.write_all(roff.as_bytes())
+        .map_err(|e| format!("Failed to write to temporary file for --full-help: {}", e))?;
+    let displayed = display_with_man(f.path(), full_help_env);
+    if let Err(e) = f.close() {
+        debug!("Failed to remove temporary file for --full-help: {}", e);
+    }
+    Ok(displayed)
+}
+
+/// Show a man page file with 'man', returning whether it was shown. See
+/// display_full_help_noexit().
+fn display_with_man(path: &Path, full_help_env: &FullHelpEnv) -> bool {
+    for (var, pager) in &full_help_env.pagers {
+        debug!("Displaying --full-help with 'man -l' and pager '{}' from ${}", pager, var);
+        if display_with_man_and_pager(path, pager, full_help_env) {
+            return true;
+        }
+    }
+    debug!("Displaying --full-help with 'man'");
+    match full_help_env.command("man").args([path]).spawn() {
+        Ok(mut child) => match child.wait() {
+            Ok(status) if status.success() => true,
+            Ok(status) => {
+                debug!("'man' failed for --full-help, exitstatus was {:?}", status);
+                false
+            }
+            Err(e) => {
+                debug!("Failed to wait for 'man' for --full-help: {}", e);
+                false
+            }
+        },
+        Err(e) => {
+            debug!("Failed to spawn 'man' command for --full-help: {}", e);
+            false
+        }
+    }
+}
+
+/// Pipe the output of 'man -l path' to pager, returning whether both
+/// succeeded.
+fn display_with_man_and_pager(path: &Path, pager: &str, full_help_env: &FullHelpEnv) -> bool {
+    let mut man = match full_help_env
+        .command("man")
+        .arg("-l")
+        .arg(path)
+        // Keep bold and underlining, as 'man' does when it runs the pager
+        .env("MAN_KEEP_FORMATTING", "1")
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Failed to spawn 'man' command for --full-help: {}", e);
+            return false;
+        }
+    };
+    let pager_child = full_help_env
+        .command("sh")
+        .args(["-c", pager])
+        .stdin(man.stdout.take().unwrap())
+        .spawn();
+    let pager_status = match pager_child {
+        Ok(mut child) => child.wait(),
+        Err(e) => {
+            debug!("Failed to spawn pager '{}' for --full-help: {}", pager, e);
+            let _ = man.kill();
+            let _ = man.wait();
+            return false;
+        }
+    };
+    match (man.wait(), pager_status) {
+        // 'man' is killed by SIGPIPE if the pager is quit before the end.
+        (Ok(man_status), Ok(pager_status))
+            if pager_status.success() && (man_status.success() || man_status.code().is_none()) =>
+        {
+            true
+        }
+        result => {
+            debug!("'man -l' with pager '{}' failed for --full-help: {:?}", pager, result);
+            false
+        }
+    }
+}
+
+fn print_full_help_text(text: &str) -> std::result::Result<(), String> {
+    write_full_help_text(&mut std::io::stdout(), text)
+}
+
+fn write_full_help_text(out: &mut dyn Write, text: &str) -> std::result::Result<(), String> {
+    out.write_all(text.as_bytes())
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to print --full-help: {}", e))
+}
+
+/// Add a hidden --generate-man-page PATH flag, which is handled by
+/// print_full_help_if_needed(), for packagers.
+pub fn add_generate_man_page_flag(cmd: clap::Command) -> clap::Command {
+    cmd.arg(
+        Arg::new("generate-man-page")
+            .long("generate-man-page")
+            .value_name("PATH")
+            .help("Write the man page to PATH, compressed with gzip if it ends in '.gz', and exit")
+            .hide(true),
+    )
+}
+
+/// Write the roff of a manual to path, e.g. 'tool-genome.1', creating its
+/// directory if needed. If gzip, the roff is gzip compressed, as distributions
+/// expect, and path should end in '.gz'. The file is written to a temporary
+/// file in the same directory which is then renamed, so path is never left
+/// partially written.
+/// The manual is taken by value as Manual cannot be cloned, and is consumed
+/// when it is rendered. To use it for something else as well, render it and
+/// write the roff with write_roff_to_file().
+pub fn write_manual_to_file(manual: Manual, path: &Path, gzip: bool) -> std::io::Result<()> {
+    write_roff_to_file(&manual.render(), path, gzip)
+}
+
+/// As write_manual_to_file(), for a manual already rendered to roff.
+pub fn write_roff_to_file(roff: &str, path: &Path, gzip: bool) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    let mut f = tempfile::NamedTempFile::new_in(dir)?;
+    match gzip {
+        true => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut f, flate2::Compression::default());
+            encoder.write_all(roff.as_bytes())?;
+            encoder.finish()?;
+        }
+        false => f.write_all(roff.as_bytes())?,
+    }
+    // Temporary files are only readable by their owner.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        f.as_file().set_permissions(std::fs::Permissions::from_mode(0o644))?;
+    }
+    f.persist(path).map_err(|e| e.error)?;
+    debug!("Wrote man page to '{}'", path.display());
+    Ok(())
+}
+
+/// Write the roff of each manual to dir, named by its name e.g.
+/// 'coverm-genome.1', or 'coverm-genome.1.gz' if gzip, as with
+/// write_manual_to_file(). dir is created if needed. The paths written are
+/// returned. If a page cannot be written, the Err says which. As for
+/// write_manual_to_file(), the manuals are taken by value because rendering
+/// consumes them.
+pub fn write_all_manuals(
+    manuals: Vec<(String, Manual)>,
+    dir: &Path,
+    gzip: bool,
+) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for (name, manual) in manuals {
+        let path = dir.join(match gzip {
+            true => format!("{}.1.gz", name),
+            false => format!("{}.1", name),
+        });
+        write_manual_to_file(manual, &path, gzip).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("Failed to write man page '{}': {}", path.display(), e),
+            )
+        })?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Add a hidden --generate-man-pages DIR flag for packagers, which is handled
+/// by generate_man_pages_if_needed(). Intended for the top level command of
+/// tools with subcommands.
+pub fn add_generate_man_pages_flag(cmd: clap::Command) -> clap::Command {
+    cmd.arg(
+        Arg::new("generate-man-pages")
+            .long("generate-man-pages")
+            .value_name("DIR")
+            .help("Write the man pages of the tool and each of its subcommands to DIR, and exit")
+            .hide(true),
+    )
+}
+
+/// If --generate-man-pages was given (see add_generate_man_pages_flag()),
+/// write the manuals made by manuals_fn to the given directory with
+/// write_all_manuals() and exit. If a page cannot be written, the problem is
+/// printed and the process exits with status 1.
+pub fn generate_man_pages_if_needed<F: FnOnce() -> Vec<(String, Manual)>>(
+    m: &clap::ArgMatches,
+    gzip: bool,
+    manuals_fn: F,
+) {
+    if let Ok(Some(dir)) = m.try_get_one::<String>("generate-man-pages") {
+        match write_all_manuals(manuals_fn(), Path::new(dir), gzip) {
+            Ok(paths) => {
+                debug!("Wrote {} man pages to '{}'", paths.len(), dir);
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Show text with $MANPAGER or $PAGER, returning false if neither is set or
+/// the pager could not be run.
+fn display_with_pager(text: &str, full_help_env: &FullHelpEnv) -> bool {
+    let pager = match full_help_env.pagers.first() {
+        Some((_, pager)) => pager,
+        None => return false,
+    };
+    debug!("Displaying --full-help with pager '{}'", pager);
+    let mut child = match full_help_env
+        .command("sh")
+        .args(["-c", pager])
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Failed to spawn pager '{}' for --full-help: {}", pager, e);
+            return false;
+        }
+    };
+    // The pager may exit before reading everything, e.g. if the user quits.
+    let _ = child.stdin.take().unwrap().write_all(text.as_bytes());
+    match child.wait() {
+        Ok(status) if status.success() => true,
+        result => {
+            debug!("Pager '{}' failed for --full-help: {:?}", pager, result);
+            false
+        }
+    }
+}
+
+/// Render a Manual as plain text, wrapped to width columns, e.g. for
+/// --full-help when stdout is not a terminal. Monospace text is not broken
+/// across lines.
+pub fn render_manual_plaintext(manual: Manual, width: usize) -> String {
+    plain_text_from_roff(&manual.render(), width)
+}
+
+fn plain_text_from_roff(roff: &str, width: usize) -> String {
+    let mut text = String::new();
+    plain_text_from_roff_blocks(&parse_manual_roff(roff), "", width, &mut text);
+    text
+}
+
+/// Width to wrap plain text --full-help to, from $COLUMNS or otherwise 100.
+fn full_help_width() -> usize {
+    match env::var("COLUMNS").ok().and_then(|columns| columns.trim().parse().ok()) {
+        Some(columns) if columns > 0 => columns,
+        _ => 100,
+    }
+}
+
+/// Escape text so that it is shown as is in a man page. Backslashes are
+/// escaped, so that e.g. font changes are shown rather than applied, and lines
+/// starting with '.' or "'", which would otherwise be treated as requests, are
+/// protected with \&. Hyphens are left alone, as bird_tool_utils_man escapes
+/// every '-' as '\-' when the Manual is rendered, so escaping them here would
+/// give '\\-', shown as a backslash followed by a hyphen. The *_roff helpers
+/// escape their input in this way, apart from list_roff_unescaped() and
+/// table_roff_unescaped(), which are for content which intentionally contains
+/// roff, e.g. from monospace_roff().
+pub fn escape_roff(s: &str) -> String {
+    let s = s.replace('\\', "\\e");
+    let mut escaped = String::new();
+    for (i, line) in s.split('\n').enumerate() {
+        if i > 0 {
+            escaped.push('\n');
+        }
+        if line.starts_with('.') || line.starts_with('\'') {
+            escaped.push_str("\\&");
+        }
+        escaped.push_str(line);
+    }
+    escaped
+}
+
+/// Check that roff, e.g. from Manual::render(), is valid, for use in the
+/// tests of tools. If groff is installed, the roff is checked with 'groff
+/// -man -Tutf8 -z', and any warnings or errors it gives are returned as an
+/// Err. Otherwise, the roff is only checked for unbalanced .TS/.TE and .nf/.fi
+/// requests, and NUL bytes.
+#[cfg(feature = "testing")]
+pub fn validate_roff(roff: &str) -> std::result::Result<(), String> {
+    match which::which("groff") {
+        Ok(groff) => validate_roff_with_groff(&groff, roff),
+        Err(_) => {
+            debug!("groff not found, so only checking the structure of the roff");
+            lint_roff(roff)
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+fn validate_roff_with_groff(groff: &Path, roff: &str) -> std::result::Result<(), String> {
+    let mut child = std::process::Command::new(groff)
+        .args(["-man", "-Tutf8", "-z"])
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run groff: {}", e))?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(roff.as_bytes())
+        .map_err(|e| format!("Failed to write roff to groff: {}", e))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for groff: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        Err(format!("groff reported problems with the roff:\n{}", stderr.trim()))
+    } else if !output.status.success() {
+        Err(format!("groff failed, exitstatus was {:?}", output.status))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check roff for problems groff would report, without groff. Each no-fill
+/// block must end before the next section heading.
+#[cfg(feature = "testing")]
+fn lint_roff(roff: &str) -> std::result::Result<(), String> {
+    let mut problems = vec![];
+    // Line numbers where a table and no-fill block start
+    let mut table_start: Option<usize> = None;
+    let mut no_fill_start: Option<usize> = None;
+    for (i, line) in roff.lines().enumerate() {
+        let line_number = i + 1;
+        if line.contains('\0') {
+            problems.push(format!("Line {} contains a NUL byte", line_number));
+        }
+        let request = match line.strip_prefix('.').or_else(|| line.strip_prefix('\'')) {
+            Some(request) => request.split_whitespace().next().unwrap_or(""),
+            None => continue,
+        };
+        if request == "SH" || request == "SS" {
+            if let Some(n) = no_fill_start.take() {
+                problems.push(format!(
+                    "Line {}: .nf without .fi before the heading on line {}",
+                    n, line_number
+                ));
+            }
+            continue;
+        }
+        let (start, block) = match request {
+            "TS" | "TE" => (&mut table_start, "table"),
+            "nf" | "fi" => (&mut no_fill_start, "no-fill block"),
+            _ => continue,
+        };
+        match (request, *start) {
+            ("TS", Some(n)) | ("nf", Some(n)) => problems.push(format!(
+                "Line {}: .{} inside the {} started on line {}",
+                line_number, request, block, n
+            )),
+            ("TS", None) | ("nf", None) => *start = Some(line_number),
+            ("TE", None) => problems.push(format!("Line {}: .TE without .TS", line_number)),
+            // .fi is also used to end filling which was never turned off
+            _ => *start = None,
+        }
+    }
+    if let Some(n) = table_start {
+        problems.push(format!("Line {}: .TS without .TE", n));
+    }
+    // A no-fill block may be left open at the end, as bird_tool_utils-man
+    // does in the AUTHOR section, which groff accepts.
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(problems.join("\n")),
+    }
+}
+
+pub fn default_roff(s: &str) -> String {
+    format!("[default: \\f[C]{}\\f[R]]", escape_roff(s))
+}
+
+/// For options which have no default value, e.g. '[default: not set]'.
+pub fn default_none_roff() -> String {
+    "[default: not set]".to_string()
+}
+
+/// For options which take multiple values, e.g. '[default: a, b, c]', with
+/// each value shown as by default_roff(). If there are no values, as
+/// default_none_roff().
+pub fn default_list_roff(values: &[&str]) -> String {
+    if values.is_empty() {
+        return default_none_roff();
+    }
+    let values: Vec<String> = values.iter().map(|v| monospace_roff(v)).collect();
+    format!("[default: {}]", values.join(", "))
+}
+
+/// default_roff() if there is a default value, otherwise
+/// default_none_roff().
+pub fn default_option_roff(default: Option<&str>) -> String {
+    match default {
+        Some(s) => default_roff(s),
+        None => default_none_roff(),
+    }
+}
+
+pub fn monospace_roff(s: &str) -> String {
+    format!("\\f[C]{}\\f[R]", escape_roff(s))
+}
+
+/// Constant width text over multiple lines e.g. an ASCII diagram, in which
+/// line breaks and spacing are kept as they are, unlike monospace_roff(). As
+/// for code_block_roff(), but not indented, so it can go within help text.
+pub fn monospace_roff_multiline(s: &str) -> String {
+    let mut roff: String = "\n.nf\n.ft C\n".to_string();
+    for line in s.lines() {
+        roff.push_str(&escape_roff(line));
+        roff.push('\n');
+    }
+    roff.push_str(".ft R\n.fi\n");
+    roff
+}
+
+/// Bold text e.g. for flags.
+pub fn bold_roff(s: &str) -> String {
+    format!("\\f[B]{}\\f[R]", escape_roff(s))
+}
+
+/// Italic text e.g. for placeholders such as PATH.
+pub fn italic_roff(s: &str) -> String {
+    format!("\\f[I]{}\\f[R]", escape_roff(s))
+}
+
+/// Bold monospace text, as flags are usually shown.
+pub fn bold_monospace_roff(s: &str) -> String {
+    format!("\\f[CB]{}\\f[R]", escape_roff(s))
+}
+
+pub fn list_roff(strings: &[&str]) -> String {
+    let items: Vec<ListItem> = strings.iter().map(|s| ListItem::Text(s.to_string())).collect();
+    list_roff_nested(&items)
+}
+
+/// Item of a list made with list_roff_nested().
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListItem {
+    Text(String),
+    /// List nested under the item before it.
+    SubList(Vec<ListItem>),
+}
+
+/// A bulleted list in the format of list_roff(), where items may have lists
+/// of their own e.g. output formats and their columns. The text of each item
+/// is escaped with escape_roff().
+pub fn list_roff_nested(items: &[ListItem]) -> String {
+    let mut s: String = "\n".to_string(); //start with a new line so the first .IP starts at the first char of the row
+    push_list_items_roff(items, &mut s);
+    s.push_str(".PP\n");
+    s
+}
+
+fn push_list_items_roff(items: &[ListItem], s: &mut String) {
+    for item in items {
+        match item {
+            ListItem::Text(text) => {
+                s.push_str(".IP \\[bu] 2\n");
+                s.push_str(&escape_roff(text));
+                s.push('\n');
+            }
+            ListItem::SubList(items) => {
+                s.push_str(".RS 2\n");
+                push_list_items_roff(items, s);
+                s.push_str(".RE\n");
+            }
+        }
+    }
+}
+
+/// A list in the format of list_roff(), but numbered rather than bulleted.
+/// Line breaks within an item are kept, with the following lines indented as
+/// for the first.
+pub fn numbered_list_roff(strings: &[&str]) -> String {
+    // Indent items by the width of the longest number
+    let indent = format!("{}.", strings.len()).len() + 1;
+    let mut s: String = "\n".to_string(); //start with a new line so the first .IP starts at the first char of the row
+    for (i, e) in strings.iter().enumerate() {
+        s.push_str(&format!(".IP {}. {}\n", i + 1, indent));
+        s.push_str(&escape_roff(e).replace('\n', "\n.br\n"));
+        s.push('\n');
+    }
+    s.push_str(".PP\n");
+    s
+}
+
+/// A list of terms, shown in bold, each followed by an indented description,
+/// e.g. for exit statuses or output columns. Both are escaped with
+/// escape_roff().
+pub fn definition_list_roff(pairs: &[(&str, &str)]) -> String {
+    let terms: Vec<String> = pairs.iter().map(|(term, _)| bold_roff(term)).collect();
+    let formatted: Vec<(&str, &str)> = terms
+        .iter()
+        .zip(pairs)
+        .map(|(term, (_, description))| (term.as_str(), *description))
+        .collect();
+    definition_list_roff_formatted(&formatted)
+}
+
+/// As definition_list_roff(), but the terms are already formatted as roff,
+/// e.g. with monospace_roff(). Descriptions are still escaped.
+pub fn definition_list_roff_formatted(pairs: &[(&str, &str)]) -> String {
+    let mut s: String = "\n".to_string(); //start with a new line so the first .TP starts at the first char of the row
+    for (term, description) in pairs {
+        s.push_str(".TP\n");
+        s.push_str(term);
+        s.push('\n');
+        s.push_str(&escape_roff(description));
+        s.push('\n');
+    }
+    s.push_str(".PP\n");
+    s
+}
+
+/// Environment variables used by this crate, with descriptions, for including
+/// in a tool's environment_section().
+pub const ENVIRONMENT_VARIABLES: &[(&str, &str)] = &[
+    (
+        "RUST_LOG",
+        "Log message filters e.g. 'debug' or 'info,hyper=warn', which override the log level set on the command line.",
+    ),
+    (
+        DEFAULT_LOG_LEVEL_ENV_VAR,
+        "Log level e.g. 'debug', used when the level is not set on the command line.",
+    ),
+    (
+        "NO_COLOR",
+        "If set, log messages are not coloured unless --color always is specified.",
+    ),
+    (
+        "TMPDIR",
+        "Directory in which temporary files are made, e.g. for genomes extracted from archives.",
+    ),
+];
+
+/// An ENVIRONMENT Section describing environment variables, e.g. RUST_LOG.
+/// Names are shown in monospace and descriptions are escaped, as in
+/// definition_list_roff(). ENVIRONMENT_VARIABLES can be included to describe
+/// those used by this crate.
+pub fn environment_section(vars: &[(&str, &str)]) -> Section {
+    let names: Vec<String> = vars.iter().map(|(name, _)| monospace_roff(name)).collect();
+    let pairs: Vec<(&str, &str)> = names
+        .iter()
+        .zip(vars)
+        .map(|(name, (_, description))| (name.as_str(), *description))
+        .collect();
+    Section::new("Environment").paragraph(&definition_list_roff_formatted(&pairs))
+}
+
+/// A link to url, shown as label if given, and otherwise as the URL itself.
+/// Terminals which support hyperlinks make it clickable when shown with man,
+/// and render_manual_markdown() and render_manual_html() make it a link.
+/// The link takes lines of its own, so text after it starts a new line, and
+/// should not start with a space, which would break the line, or with '.',
+/// unless escaped with escape_roff().
+pub fn url_roff(url: &str, label: Option<&str>) -> String {
+    link_roff("UR", "UE", url, label, "")
+}
+
+/// As url_roff(), but for an email address, which is linked to with mailto.
+pub fn mailto_roff(address: &str, label: Option<&str>) -> String {
+    link_roff("MT", "ME", address, label, "")
+}
+
+/// A link, directly followed by suffix e.g. punctuation.
+fn link_roff(start: &str, end: &str, target: &str, label: Option<&str>, suffix: &str) -> String {
+    // Link targets are request arguments, so they cannot contain spaces.
+    let mut s = format!("\n.{} {}\n", start, escape_roff(target).replace(' ', "%20"));
+    if let Some(label) = label {
+        s.push_str(&escape_roff(label));
+        s.push('\n');
+    }
+    s.push_str(&format!(".{}", end));
+    if !suffix.is_empty() {
+        s.push_str(&format!(" {}", escape_roff(suffix).replace(' ', "")));
+    }
+    s.push('\n');
+    s
+}
+
+/// A CITATION Section, with each citation as a paragraph. DOIs, e.g.
+/// 'doi:10.1038/s41587-021-01011-1', '10.1038/s41587-021-01011-1' or
+/// 'https://doi.org/10.1038/s41587-021-01011-1', are made into links with
+/// url_roff().
+pub fn citation_section(citations: &[&str]) -> Section {
+    let mut section = Section::new("Citation");
+    for citation in citations {
+        let mut paragraph = String::new();
+        for word in citation.split_whitespace() {
+            let trimmed = word.trim_end_matches(['.', ',', ';', ')']);
+            match doi(trimmed) {
+                Some(doi) => paragraph.push_str(&link_roff(
+                    "UR",
+                    "UE",
+                    &format!("https://doi.org/{}", doi),
+                    Some(trimmed),
+                    &word[trimmed.len()..],
+                )),
+                None => {
+                    if !paragraph.is_empty() && !paragraph.ends_with('\n') {
+                        paragraph.push(' ');
+                    }
+                    paragraph.push_str(&escape_roff(word));
+                }
+            }
+        }
+        section = section.paragraph(&paragraph);
+    }
+    section
+}
+
+/// The DOI in a word of a citation, if any.
+fn doi(word: &str) -> Option<&str> {
+    let lower = word.to_lowercase();
+    let doi = ["https://doi.org/", "http://doi.org/", "https://dx.doi.org/", "doi:"]
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))
+        .map_or(word, |prefix| &word[prefix.len()..]);
+    // DOIs are of the form 10.NNNN/suffix
+    match doi.strip_prefix("10.").and_then(|rest| rest.split_once('/')) {
+        Some((registrant, suffix))
+            if !registrant.is_empty()
+                && registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && !suffix.is_empty() =>
+        {
+            Some(doi)
+        }
+        _ => None,
+    }
+}
+
+/// A SEE ALSO Section listing related man pages, e.g. ('samtools', 1), shown
+/// as a comma separated list in the man page convention 'samtools(1)'.
+pub fn see_also_section(pages: &[(&str, u8)]) -> Section {
+    let pages: Vec<String> = pages
+        .iter()
+        .map(|(name, section)| format!("{}({})", bold_roff(name), section))
+        .collect();
+    Section::new("See also").paragraph(&pages.join(", "))
+}
+
+/// An indented block of constant width text, e.g. example commands, in which
+/// line breaks and spacing are kept as they are. Lines are escaped with
+/// escape_roff(), so e.g. paths starting with './' are shown correctly.
+pub fn code_block_roff(lines: &[&str]) -> String {
+    let mut s: String = "\n.RS 4\n.nf\n.ft C\n".to_string();
+    for line in lines {
+        s.push_str(&escape_roff(line));
+        s.push('\n');
+    }
+    s.push_str(".ft R\n.fi\n.RE\n");
+    s
+}
+
+/// An EXAMPLES section in which each description is followed by its command,
+/// shown with code_block_roff().
+pub fn example_section(examples: &[(&str, &str)]) -> Section {
+    examples
+        .iter()
+        .fold(ExamplesSection::default(), |section, (description, command)| {
+            section.example(description, command)
+        })
+        .section()
+}
+
+/// Start building an EXAMPLES section, e.g.
+/// examples().example("Map reads", "coverm genome -f a.fna -1 r1.fq").section()
+pub fn examples() -> ExamplesSection {
+    ExamplesSection::default()
+}
+
+/// Builder for an EXAMPLES section, in which each description is a paragraph
+/// followed by its command, shown with code_block_roff(). It can be made into
+/// a Section for a Manual, or rendered as Markdown e.g. for a README.
+#[derive(Debug, Clone, Default)]
+pub struct ExamplesSection {
+    examples: Vec<(String, String)>,
+}
+
+impl ExamplesSection {
+    pub fn example(mut self, description: &str, command: &str) -> ExamplesSection {
+        self.examples.push((description.to_string(), command.to_string()));
+        self
+    }
+
+    pub fn section(&self) -> Section {
+        let mut section = Section::new("Examples");
+        for (description, command) in &self.examples {
+            section = section.paragraph(&format!(
+                "{}{}",
+                escape_roff(description),
+                code_block_roff(&[command])
+            ));
+        }
+        section
+    }
+
+    pub fn render_markdown(&self) -> String {
+        let mut md = "## Examples\n".to_string();
+        for (description, command) in &self.examples {
+            md.push_str(&format!(
+                "\n{}\n\n```\n{}\n```\n",
+                escape_markdown(description),
+                command
+            ));
+        }
+        md
+    }
+}
+
+impl From<ExamplesSection> for Section {
+    fn from(examples: ExamplesSection) -> Section {
+        examples.section()
+    }
+}
+
+/// As list_roff(), but the items are not escaped, for items which contain
+/// roff.
+pub fn list_roff_unescaped(strings: &[&str]) -> String {
+    let mut s: String = "\n".to_string(); //start with a new line so the first .IP starts at the first char of the row
+    for e in strings {
+        s.push_str(".IP \\[bu] 2\n");
         s.push_str(e);
-        s.push_str("\n");
+        s.push('\n');
     }
     s.push_str(".PP\n");
     s
 }
 
 pub fn table_roff(strings: &[&[&str]]) -> String {
+    table_roff_options(strings, &TableOptions::default())
+}
+
+/// As table_roff(), but the cells are not escaped, for cells which contain
+/// roff.
+pub fn table_roff_unescaped(strings: &[&[&str]]) -> String {
+    table_roff_options_unescaped(strings, &TableOptions::default())
+}
+
+/// Alignment of a column of a table made with table_roff_options().
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableAlignment {
+    #[default]
+    Left,
+    Right,
+    Center,
+}
+
+/// Options for tables made with table_roff_options(). The default is as for
+/// table_roff().
+#[derive(Debug, Clone, Default)]
+pub struct TableOptions {
+    /// Whether the first row is a header, which is shown in bold. The first
+    /// row is underlined either way, as by table_roff().
+    pub has_header: bool,
+    /// Alignment of each column. Columns without one are left aligned.
+    pub alignments: Vec<TableAlignment>,
+    /// Minimum width of each column in characters (ens), if any. For columns
+    /// which wrap, this is the width text is wrapped to.
+    pub widths: Vec<Option<usize>>,
+    /// Columns (counting from 0) whose text is wrapped rather than being
+    /// allowed to run off the page. If None, the last column wraps when the
+    /// table would otherwise be wider than TABLE_WIDTH.
+    pub wrap_columns: Option<Vec<usize>>,
+}
+
+/// Width in characters (ens) which tables are fit to by wrapping columns, as
+/// man pages are often read in 80 column terminals and tables are indented.
+pub const TABLE_WIDTH: usize = 70;
+
+/// Space tbl leaves between columns, in ens.
+const TABLE_COLUMN_SEPARATION: usize = 3;
+
+/// A table in the format of table_roff(), but with a header, column
+/// alignment and widths as specified in options.
+pub fn table_roff_options(strings: &[&[&str]], options: &TableOptions) -> String {
+    let escaped: Vec<Vec<String>> = strings
+        .iter()
+        .map(|row| row.iter().map(|cell| escape_roff(cell)).collect())
+        .collect();
+    let rows: Vec<Vec<&str>> = escaped
+        .iter()
+        .map(|row| row.iter().map(|cell| cell.as_str()).collect())
+        .collect();
+    let rows: Vec<&[&str]> = rows.iter().map(|row| row.as_slice()).collect();
+    table_roff_options_unescaped(&rows, options)
+}
+
+/// As table_roff_options(), but the cells are not escaped, for cells which
+/// contain roff.
+pub fn table_roff_options_unescaped(strings: &[&[&str]], options: &TableOptions) -> String {
+    // Rows with fewer cells than the longest are padded with empty cells, as
+    // tbl requires each row to match the format line.
+    let num_columns = strings.iter().map(|row| row.len()).max().unwrap_or(0);
+    if num_columns == 0 {
+        return String::new();
+    }
+    let num_short_rows = strings.iter().filter(|row| row.len() < num_columns).count();
+    if num_short_rows > 0 {
+        debug!(
+            "Padding {} table row(s) with empty cells to {} columns",
+            num_short_rows, num_columns
+        );
+    }
     //start with a new line so the first .IP starts at the first char of the row
     let mut s: String = "\n.TS\n\
         tab(@);\n"
         .to_string();
-    for row in strings {
-        for _ in *row {
-            s.push_str("l ");
+    let widths = table_column_widths(strings, num_columns, options);
+    // Alignment and width of each column e.g. ('r', "w(10n)")
+    let mut formats = vec![];
+    for (i, width) in widths.iter().enumerate() {
+        let alignment = match options.alignments.get(i).copied().unwrap_or_default() {
+            TableAlignment::Left => 'l',
+            TableAlignment::Right => 'r',
+            TableAlignment::Center => 'c',
+        };
+        let width = match width {
+            Some(width) => format!("w({}n)", width),
+            None => String::new(),
+        };
+        formats.push((alignment, width));
+    }
+    if options.has_header {
+        // The first format line applies to the first row, and the last to the
+        // rest.
+        for (alignment, width) in &formats {
+            s.push_str(&format!("{}b{} ", alignment, width));
         }
-        break;
+        s.push('\n');
+    }
+    for (alignment, width) in &formats {
+        s.push_str(&format!("{}{} ", alignment, width));
     }
     s.push_str(".\n");
 
@@ -269,18 +2636,2394 @@ pub fn table_roff(strings: &[&[&str]]) -> String {
             if first_column {
                 first_column = false;
             } else {
-                s.push_str("@");
+                s.push('@');
             }
             s.push_str("T{\n");
             s.push_str(cell);
             s.push_str("\nT}");
         }
-        s.push_str("\n");
+        for _ in e.len()..num_columns {
+            if first_column {
+                first_column = false;
+            } else {
+                s.push('@');
+            }
+            s.push_str("T{\nT}");
+        }
+        s.push('\n');
         if first_row {
-            first_row = false;
             s.push_str("_\n");
         }
+        first_row = false;
     }
     s.push_str(".TE\n");
     s
 }
+
+/// Width of each column of a table as given in options, with wrapping
+/// columns given the space left over by the others if they have no width.
+/// tbl wraps the text blocks of columns with a width to that width.
+fn table_column_widths(
+    strings: &[&[&str]],
+    num_columns: usize,
+    options: &TableOptions,
+) -> Vec<Option<usize>> {
+    let mut widths: Vec<Option<usize>> =
+        (0..num_columns).map(|i| options.widths.get(i).copied().flatten()).collect();
+    // Width of the longest cell of each column, as shown
+    let mut natural_widths = vec![0; num_columns];
+    for row in strings {
+        for (i, cell) in row.iter().enumerate() {
+            let width = roff_plain_text(&parse_roff_inline(cell)).chars().count();
+            natural_widths[i] = natural_widths[i].max(width);
+        }
+    }
+    let separation = TABLE_COLUMN_SEPARATION * (num_columns - 1);
+    let wrap_columns: Vec<usize> = match &options.wrap_columns {
+        Some(columns) => columns.iter().copied().filter(|i| *i < num_columns).collect(),
+        None => {
+            let table_width: usize = (0..num_columns)
+                .map(|i| widths[i].unwrap_or(0).max(natural_widths[i]))
+                .sum();
+            match table_width + separation > TABLE_WIDTH {
+                true => vec![num_columns - 1],
+                false => vec![],
+            }
+        }
+    };
+    let unsized_wrap_columns: Vec<usize> =
+        wrap_columns.iter().copied().filter(|i| widths[*i].is_none()).collect();
+    if unsized_wrap_columns.is_empty() {
+        return widths;
+    }
+    let other_columns_width: usize = (0..num_columns)
+        .filter(|i| !unsized_wrap_columns.contains(i))
+        .map(|i| widths[i].unwrap_or(0).max(natural_widths[i]))
+        .sum();
+    let wrap_width = TABLE_WIDTH.saturating_sub(other_columns_width + separation)
+        / unsized_wrap_columns.len();
+    // Narrow columns are wrapped less to stay readable
+    let wrap_width = wrap_width.max(20);
+    for i in unsized_wrap_columns {
+        let width = wrap_width.min(natural_widths[i]).max(1);
+        debug!("Wrapping table column {} to {} characters", i + 1, width);
+        widths[i] = Some(width);
+    }
+    widths
+}
+
+/// Render a Manual as GitHub-flavoured Markdown, e.g. for a documentation
+/// website. The roff the Manual renders to is converted, so help text built
+/// with monospace_roff(), list_roff() and table_roff() comes out as Markdown
+/// code, lists and tables. Sections become '##' headings and flags and options
+/// become a list of backticked flags, each followed by its help.
+pub fn render_manual_markdown(manual: Manual) -> String {
+    markdown_from_roff(&manual.render())
+}
+
+fn markdown_from_roff(roff: &str) -> String {
+    let mut md = String::new();
+    markdown_from_roff_blocks(&parse_manual_roff(roff), "", &mut md);
+    md
+}
+
+/// Render a Manual as a standalone HTML page, e.g. for a documentation
+/// website, without needing groff. As for render_manual_markdown(), the roff
+/// the Manual renders to is converted. Sections become h2 headings, flags and
+/// options are listed in tables, and monospace text is shown in code elements.
+pub fn render_manual_html(manual: Manual) -> String {
+    html_from_roff(&manual.render())
+}
+
+fn html_from_roff(roff: &str) -> String {
+    let blocks = parse_manual_roff(roff);
+    let title = blocks
+        .iter()
+        .find_map(|block| match block {
+            RoffBlock::Title { name, section } => Some(manual_title(name, section)),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n",
+        escape_html(&title)
+    );
+    html_from_roff_blocks(&blocks, &mut html);
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+pub fn monospace_md(s: &str) -> String {
+    match s.contains('`') {
+        true => format!("`` {} ``", s),
+        false => format!("`{}`", s),
+    }
+}
+
+pub fn list_md(strings: &[&str]) -> String {
+    let mut s: String = "\n\n".to_string(); //start with a blank line so the list is not part of the previous paragraph
+    for e in strings {
+        s.push_str("- ");
+        s.push_str(e);
+        s.push('\n');
+    }
+    s.push('\n');
+    s
+}
+
+pub fn table_md(strings: &[&[&str]]) -> String {
+    //start with a blank line so the table is not part of the previous paragraph
+    let mut s: String = "\n\n".to_string();
+    let mut first_row = true;
+    for e in strings {
+        s.push_str(&markdown_table_row(e));
+        if first_row {
+            first_row = false;
+            s.push('|');
+            for _ in *e {
+                s.push_str(" --- |");
+            }
+            s.push('\n');
+        }
+    }
+    s.push('\n');
+    s
+}
+
+fn markdown_table_row<S: AsRef<str>>(cells: &[S]) -> String {
+    let mut s = "|".to_string();
+    for cell in cells {
+        s.push(' ');
+        s.push_str(&cell.as_ref().replace('|', "\\|"));
+        s.push_str(" |");
+    }
+    s.push('\n');
+    s
+}
+
+/// Font of a run of text in a manual.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RoffFont {
+    Regular,
+    Bold,
+    Italic,
+    Monospace,
+}
+
+/// Inline markup of a manual, as parsed from its roff so that it can be
+/// rendered in other formats.
+#[derive(Debug, PartialEq)]
+enum RoffInline {
+    Text(RoffFont, String),
+    Link { url: String, label: String },
+}
+
+/// A line of text, and whether it is followed by a line break rather than
+/// being filled with the next line.
+type RoffLine = (Vec<RoffInline>, bool);
+
+/// Block of a manual, as parsed from its roff.
+#[derive(Debug, PartialEq)]
+enum RoffBlock {
+    Title { name: String, section: Option<String> },
+    Heading(String),
+    Paragraph(Vec<RoffLine>),
+    /// Items of a bulleted or numbered list, each with its depth of nesting.
+    List { numbered: bool, items: Vec<(usize, Vec<RoffLine>)> },
+    Table(Vec<Vec<Vec<RoffInline>>>),
+    /// Lines of constant width text, shown as is.
+    Code(Vec<String>),
+    /// Flag, option, exit status or example, described by the blocks which
+    /// follow it.
+    Term { term: Vec<RoffInline>, blocks: Vec<RoffBlock> },
+}
+
+/// Parse the subset of roff produced by bird_tool_utils_man and the roff
+/// helpers above. Unknown requests are ignored.
+fn parse_manual_roff(roff: &str) -> Vec<RoffBlock> {
+    let mut parser = RoffParser::default();
+    // Rows of the table being read, if any, and the lines of the cell being
+    // read.
+    let mut table: Option<Vec<Vec<Vec<RoffInline>>>> = None;
+    let mut table_format_read = false;
+    let mut cell: Option<Vec<&str>> = None;
+    // URL and label lines of the link being read.
+    let mut link: Option<(String, Vec<&str>)> = None;
+    // Lines of the constant width no-fill block being read, if any.
+    let mut code: Option<Vec<String>> = None;
+
+    for line in roff.lines() {
+        if let Some(rows) = table.as_mut() {
+            if let Some(lines) = cell.as_mut() {
+                match line.strip_prefix("T}") {
+                    Some(rest) => {
+                        rows.last_mut().unwrap().push(parse_roff_inline(&lines.join(" ")));
+                        cell = match rest.strip_prefix('@') {
+                            Some("T{") => Some(vec![]),
+                            _ => None,
+                        };
+                    }
+                    None => lines.push(line),
+                }
+            } else if !table_format_read {
+                // Options and column formats, the last of which ends with '.'
+                table_format_read = line.trim_end().ends_with('.');
+            } else if line == ".TE" {
+                parser.add_block(RoffBlock::Table(table.take().unwrap()));
+            } else if line != "_" {
+                let mut row = vec![];
+                for part in line.split('@') {
+                    match part {
+                        "T{" => cell = Some(vec![]),
+                        _ => row.push(parse_roff_inline(part)),
+                    }
+                }
+                rows.push(row);
+            }
+            continue;
+        }
+
+        let request = match line.strip_prefix('.') {
+            Some(request) => request,
+            None => {
+                match link.as_mut() {
+                    Some((_, label)) => label.push(line),
+                    None if code.is_some() => code
+                        .as_mut()
+                        .unwrap()
+                        .push(roff_plain_text(&parse_roff_inline(line))),
+                    None if line.trim().is_empty() => parser.end_paragraph(),
+                    None => parser.add_line(parse_roff_inline(line)),
+                }
+                continue;
+            }
+        };
+        let mut words = request.splitn(2, ' ');
+        let name = words.next().unwrap_or("");
+        let arguments = words.next().unwrap_or("").trim();
+        match name {
+            "TH" => {
+                let mut arguments = arguments.split_whitespace();
+                parser.blocks.push(RoffBlock::Title {
+                    name: arguments.next().unwrap_or("").to_string(),
+                    section: arguments.next().map(|s| s.to_string()),
+                });
+            }
+            "SH" => {
+                parser.blocks.push(RoffBlock::Heading(arguments.to_string()));
+                parser.in_term = false;
+                parser.term_pending = false;
+                parser.end_paragraph();
+            }
+            "TP" => {
+                parser.in_term = false;
+                parser.term_pending = true;
+                parser.end_paragraph();
+            }
+            "IP" => {
+                // e.g. '.IP \[bu] 2' or '.IP 1. 3'
+                let tag = arguments.split_whitespace().next().unwrap_or("");
+                let numbered = tag
+                    .strip_suffix('.')
+                    .is_some_and(|number| number.parse::<usize>().is_ok());
+                if numbered != parser.list_numbered {
+                    parser.in_list = false;
+                }
+                parser.in_paragraph = false;
+                parser.list_item_pending = true;
+                parser.list_numbered = numbered;
+            }
+            "PP" | "P" | "LP" => {
+                // As in man, these also end the indentation of a term's
+                // description.
+                parser.in_term = false;
+                parser.end_paragraph();
+            }
+            // Nested lists, as made by list_roff_nested()
+            "RS" if parser.in_list => parser.list_depth += 1,
+            "RE" if parser.in_list => parser.list_depth = parser.list_depth.saturating_sub(1),
+            "sp" => parser.end_paragraph(),
+            "br" => parser.line_break(),
+            "nf" => parser.no_fill = true,
+            "fi" => {
+                parser.no_fill = false;
+                if let Some(lines) = code.take() {
+                    parser.add_block(RoffBlock::Code(lines));
+                }
+            }
+            "ft" => match code.take() {
+                Some(lines) => parser.add_block(RoffBlock::Code(lines)),
+                None if parser.no_fill && arguments.starts_with('C') => code = Some(vec![]),
+                None => {}
+            },
+            "TS" => {
+                table = Some(vec![]);
+                table_format_read = false;
+            }
+            // Hyphens in URLs are escaped by bird_tool_utils_man
+            "UR" => link = Some((roff_plain_text(&parse_roff_inline(arguments)), vec![])),
+            "MT" => {
+                let address = roff_plain_text(&parse_roff_inline(arguments));
+                link = Some((format!("mailto:{}", address), vec![]))
+            }
+            "UE" | "ME" => {
+                if let Some((url, label)) = link.take() {
+                    let label = match label.is_empty() {
+                        true => url.trim_start_matches("mailto:").to_string(),
+                        false => roff_plain_text(&parse_roff_inline(&label.join(" "))),
+                    };
+                    let mut inlines = vec![RoffInline::Link { url, label }];
+                    // Punctuation directly following the link
+                    if !arguments.is_empty() {
+                        let punctuation = roff_plain_text(&parse_roff_inline(arguments));
+                        inlines.push(RoffInline::Text(RoffFont::Regular, punctuation));
+                    }
+                    parser.add_line(inlines);
+                }
+            }
+            _ => {}
+        }
+    }
+    parser.blocks
+}
+
+#[derive(Default)]
+struct RoffParser {
+    blocks: Vec<RoffBlock>,
+    /// Whether blocks describe the last term.
+    in_term: bool,
+    /// Whether the next line is a term.
+    term_pending: bool,
+    /// Whether lines continue the last paragraph or list item.
+    in_paragraph: bool,
+    in_list: bool,
+    list_item_pending: bool,
+    list_numbered: bool,
+    /// Number of lists the current list is nested in.
+    list_depth: usize,
+    no_fill: bool,
+}
+
+impl RoffParser {
+    /// Blocks which new blocks are added to.
+    fn target(&mut self) -> &mut Vec<RoffBlock> {
+        if self.in_term && matches!(self.blocks.last(), Some(RoffBlock::Term { .. })) {
+            match self.blocks.last_mut() {
+                Some(RoffBlock::Term { blocks, .. }) => return blocks,
+                _ => unreachable!(),
+            }
+        }
+        &mut self.blocks
+    }
+
+    fn add_block(&mut self, block: RoffBlock) {
+        self.target().push(block);
+        self.end_paragraph();
+    }
+
+    fn add_line(&mut self, mut inlines: Vec<RoffInline>) {
+        if self.term_pending {
+            // e.g. ' \fIINPUT\fR' for an Opt with no flags
+            if let Some(RoffInline::Text(_, text)) = inlines.first_mut() {
+                *text = text.trim_start().to_string();
+            }
+            self.blocks.push(RoffBlock::Term {
+                term: inlines,
+                blocks: vec![],
+            });
+            self.in_term = true;
+            self.term_pending = false;
+            return;
+        }
+        let line = (inlines, self.no_fill);
+        let continue_list = self.in_list;
+        let continue_paragraph = self.in_paragraph;
+        let list_item = self.list_item_pending;
+        let numbered = self.list_numbered;
+        let depth = self.list_depth;
+        let target = self.target();
+        if list_item {
+            match (continue_list, target.last_mut()) {
+                (true, Some(RoffBlock::List { items, .. })) => items.push((depth, vec![line])),
+                _ => target.push(RoffBlock::List {
+                    numbered,
+                    items: vec![(depth, vec![line])],
+                }),
+            }
+        } else if continue_paragraph {
+            match target.last_mut() {
+                Some(RoffBlock::Paragraph(lines)) => lines.push(line),
+                Some(RoffBlock::List { items, .. }) => items.last_mut().unwrap().1.push(line),
+                _ => target.push(RoffBlock::Paragraph(vec![line])),
+            }
+        } else {
+            target.push(RoffBlock::Paragraph(vec![line]));
+        }
+        self.in_paragraph = true;
+        if list_item {
+            self.in_list = true;
+            self.list_item_pending = false;
+        }
+    }
+
+    fn line_break(&mut self) {
+        if !self.in_paragraph {
+            return;
+        }
+        let last_line = match self.target().last_mut() {
+            Some(RoffBlock::Paragraph(lines)) => lines.last_mut(),
+            Some(RoffBlock::List { items, .. }) => {
+                items.last_mut().and_then(|(_, lines)| lines.last_mut())
+            }
+            _ => None,
+        };
+        if let Some((_, line_break)) = last_line {
+            *line_break = true;
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        self.in_paragraph = false;
+        self.in_list = false;
+        self.list_item_pending = false;
+        self.list_depth = 0;
+    }
+}
+
+/// Parse roff font changes and escapes in a line of text.
+fn parse_roff_inline(line: &str) -> Vec<RoffInline> {
+    let mut inlines = vec![];
+    let mut font = RoffFont::Regular;
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => {
+                let name: String = match chars.next() {
+                    Some('[') => chars.by_ref().take_while(|c| *c != ']').collect(),
+                    Some('(') => chars.by_ref().take(2).collect(),
+                    Some(c) => c.to_string(),
+                    None => String::new(),
+                };
+                if !text.is_empty() {
+                    inlines.push(RoffInline::Text(font, std::mem::take(&mut text)));
+                }
+                font = match name.as_str() {
+                    "B" => RoffFont::Bold,
+                    "I" => RoffFont::Italic,
+                    "C" | "CR" | "CW" | "CB" | "CI" => RoffFont::Monospace,
+                    _ => RoffFont::Regular,
+                };
+            }
+            Some(c @ '[') | Some(c @ '(') => {
+                let name: String = match c {
+                    '[' => chars.by_ref().take_while(|c| *c != ']').collect(),
+                    _ => chars.by_ref().take(2).collect(),
+                };
+                match name.as_str() {
+                    "bu" => text.push('•'),
+                    "em" => text.push('—'),
+                    "en" => text.push('–'),
+                    _ => {}
+                }
+            }
+            Some('e') => text.push('\\'),
+            Some('&') => {}
+            Some(c) => text.push(c),
+            None => {}
+        }
+    }
+    if !text.is_empty() {
+        inlines.push(RoffInline::Text(font, text));
+    }
+    inlines
+}
+
+fn roff_plain_text(inlines: &[RoffInline]) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            RoffInline::Text(_, text) => text.as_str(),
+            RoffInline::Link { label, .. } => label.as_str(),
+        })
+        .collect()
+}
+
+/// e.g. 'mytool(1)'
+fn manual_title(name: &str, section: &Option<String>) -> String {
+    match section {
+        Some(section) => format!("{}({})", name.to_lowercase(), section),
+        None => name.to_lowercase(),
+    }
+}
+
+/// 'GENOME INPUT' -> 'Genome input', as roff section headings are upper case.
+fn sentence_case(s: &str) -> String {
+    let lower = s.to_lowercase();
+    let mut chars = lower.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => lower,
+    }
+}
+
+fn markdown_from_roff_blocks(blocks: &[RoffBlock], indent: &str, md: &mut String) {
+    for block in blocks {
+        if !md.is_empty() {
+            while !md.ends_with("\n\n") {
+                md.push('\n');
+            }
+        }
+        match block {
+            RoffBlock::Title { name, section } => {
+                md.push_str(&format!("# {}\n", manual_title(name, section)))
+            }
+            RoffBlock::Heading(heading) => md.push_str(&format!("## {}\n", sentence_case(heading))),
+            RoffBlock::Paragraph(lines) => markdown_lines(lines, indent, indent, md),
+            RoffBlock::List { numbered, items } => {
+                for (i, (depth, lines)) in items.iter().enumerate() {
+                    let marker = match numbered {
+                        true => format!("{}. ", i + 1),
+                        false => "- ".to_string(),
+                    };
+                    let indent = format!("{}{}", indent, " ".repeat(marker.len() * depth));
+                    markdown_lines(
+                        lines,
+                        &format!("{}{}", indent, marker),
+                        &format!("{}{}", indent, " ".repeat(marker.len())),
+                        md,
+                    );
+                }
+            }
+            RoffBlock::Code(lines) => {
+                md.push_str(&format!("{}```\n", indent));
+                for line in lines {
+                    md.push_str(format!("{}{}", indent, line).trim_end());
+                    md.push('\n');
+                }
+                md.push_str(&format!("{}```\n", indent));
+            }
+            RoffBlock::Table(rows) => {
+                let rows: Vec<Vec<String>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| markdown_inline(cell, false)).collect())
+                    .collect();
+                if let Some(header) = rows.first() {
+                    md.push_str(indent);
+                    md.push_str(&markdown_table_row(header));
+                    md.push_str(indent);
+                    md.push('|');
+                    md.push_str(&" --- |".repeat(header.len()));
+                    md.push('\n');
+                    for row in &rows[1..] {
+                        md.push_str(indent);
+                        md.push_str(&markdown_table_row(row));
+                    }
+                }
+            }
+            RoffBlock::Term { term, blocks } => {
+                md.push_str(&format!("{}- {}\n", indent, markdown_inline(term, true)));
+                markdown_from_roff_blocks(blocks, &format!("{}  ", indent), md);
+            }
+        }
+    }
+}
+
+fn markdown_lines(lines: &[RoffLine], first_indent: &str, indent: &str, md: &mut String) {
+    for (i, (inlines, line_break)) in lines.iter().enumerate() {
+        md.push_str(match i {
+            0 => first_indent,
+            _ => indent,
+        });
+        md.push_str(&markdown_inline(inlines, false));
+        if *line_break && i + 1 < lines.len() {
+            md.push('\\');
+        }
+        md.push('\n');
+    }
+}
+
+/// Markdown for inline markup. If bold_as_code, bold text is shown as code, as
+/// for flag names.
+fn markdown_inline(inlines: &[RoffInline], bold_as_code: bool) -> String {
+    let mut s = String::new();
+    for inline in inlines {
+        match inline {
+            RoffInline::Text(font, text) => match font {
+                RoffFont::Bold if bold_as_code => s.push_str(&monospace_md(text)),
+                RoffFont::Regular => s.push_str(&escape_markdown(text)),
+                RoffFont::Bold => s.push_str(&format!("**{}**", escape_markdown(text))),
+                RoffFont::Italic => s.push_str(&format!("*{}*", escape_markdown(text))),
+                RoffFont::Monospace => s.push_str(&monospace_md(text)),
+            },
+            RoffInline::Link { url, label } if url.trim_start_matches("mailto:") == label => {
+                s.push_str(&format!("<{}>", label.replace(' ', "%20")))
+            }
+            RoffInline::Link { url, label } => {
+                s.push_str(&format!("[{}]({})", escape_markdown(label), url.replace(' ', "%20")))
+            }
+        }
+    }
+    s
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '<') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn html_from_roff_blocks(blocks: &[RoffBlock], html: &mut String) {
+    let mut in_terms = false;
+    for block in blocks {
+        let is_term = matches!(block, RoffBlock::Term { .. });
+        if in_terms && !is_term {
+            html.push_str("</table>\n");
+        }
+        match block {
+            RoffBlock::Title { name, section } => html.push_str(&format!(
+                "<h1>{}</h1>\n",
+                escape_html(&manual_title(name, section))
+            )),
+            RoffBlock::Heading(heading) => {
+                html.push_str(&format!("<h2>{}</h2>\n", escape_html(&sentence_case(heading))))
+            }
+            RoffBlock::Paragraph(lines) => {
+                html.push_str(&format!("<p>{}</p>\n", html_lines(lines)))
+            }
+            RoffBlock::List { numbered, items } => {
+                let tag = match numbered {
+                    true => "ol",
+                    false => "ul",
+                };
+                html.push_str(&format!("<{}>\n", tag));
+                let mut current_depth = 0;
+                for (i, (depth, lines)) in items.iter().enumerate() {
+                    if i > 0 {
+                        if *depth > current_depth {
+                            // Nested lists go inside the item before
+                            html.push('\n');
+                            html.push_str(&"<ul>\n".repeat(depth - current_depth));
+                        } else {
+                            html.push_str("</li>\n");
+                            html.push_str(&"</ul>\n</li>\n".repeat(current_depth - depth));
+                        }
+                    }
+                    current_depth = *depth;
+                    html.push_str(&format!("<li>{}", html_lines(lines)));
+                }
+                if !items.is_empty() {
+                    html.push_str("</li>\n");
+                }
+                html.push_str(&"</ul>\n</li>\n".repeat(current_depth));
+                html.push_str(&format!("</{}>\n", tag));
+            }
+            RoffBlock::Code(lines) => html.push_str(&format!(
+                "<pre><code>{}</code></pre>\n",
+                escape_html(&lines.join("\n"))
+            )),
+            RoffBlock::Table(rows) => {
+                html.push_str("<table>\n");
+                for (i, row) in rows.iter().enumerate() {
+                    let tag = match i {
+                        0 => "th",
+                        _ => "td",
+                    };
+                    html.push_str("<tr>");
+                    for cell in row {
+                        html.push_str(&format!("<{}>{}</{}>", tag, html_inline(cell, false), tag));
+                    }
+                    html.push_str("</tr>\n");
+                }
+                html.push_str("</table>\n");
+            }
+            RoffBlock::Term { term, blocks } => {
+                if !in_terms {
+                    html.push_str("<table>\n");
+                }
+                html.push_str(&format!("<tr>\n<td>{}</td>\n<td>\n", html_inline(term, true)));
+                html_from_roff_blocks(blocks, html);
+                html.push_str("</td>\n</tr>\n");
+            }
+        }
+        in_terms = is_term;
+    }
+    if in_terms {
+        html.push_str("</table>\n");
+    }
+}
+
+fn html_lines(lines: &[RoffLine]) -> String {
+    let mut s = String::new();
+    for (i, (inlines, line_break)) in lines.iter().enumerate() {
+        s.push_str(&html_inline(inlines, false));
+        if i + 1 < lines.len() {
+            match line_break {
+                true => s.push_str("<br>\n"),
+                false => s.push('\n'),
+            }
+        }
+    }
+    s
+}
+
+/// HTML for inline markup. If bold_as_code, bold text is shown as code, as for
+/// flag names.
+fn html_inline(inlines: &[RoffInline], bold_as_code: bool) -> String {
+    let mut s = String::new();
+    for inline in inlines {
+        match inline {
+            RoffInline::Text(font, text) => {
+                let text = escape_html(text);
+                match font {
+                    RoffFont::Bold if bold_as_code => s.push_str(&format!("<code>{}</code>", text)),
+                    RoffFont::Regular => s.push_str(&text),
+                    RoffFont::Bold => s.push_str(&format!("<b>{}</b>", text)),
+                    RoffFont::Italic => s.push_str(&format!("<i>{}</i>", text)),
+                    RoffFont::Monospace => s.push_str(&format!("<code>{}</code>", text)),
+                }
+            }
+            RoffInline::Link { url, label } => s.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(url),
+                escape_html(label)
+            )),
+        }
+    }
+    s
+}
+
+fn plain_text_from_roff_blocks(
+    blocks: &[RoffBlock],
+    indent: &str,
+    width: usize,
+    text: &mut String,
+) {
+    // Indentation of section contents and term descriptions
+    let nested_indent = format!("{}    ", indent);
+    // As in man, blocks directly follow headings and terms.
+    let mut separate = false;
+    for block in blocks {
+        if separate {
+            text.push('\n');
+        }
+        separate = !matches!(block, RoffBlock::Heading(_));
+        match block {
+            RoffBlock::Title { name, section } => {
+                text.push_str(&format!("{}\n", manual_title(name, section).to_uppercase()))
+            }
+            RoffBlock::Heading(heading) => text.push_str(&format!("{}\n", heading)),
+            RoffBlock::Paragraph(lines) => {
+                plain_text_lines(lines, &nested_indent, &nested_indent, width, text)
+            }
+            RoffBlock::List { numbered, items } => {
+                for (i, (depth, lines)) in items.iter().enumerate() {
+                    let marker = match numbered {
+                        true => format!("{}. ", i + 1),
+                        false => "• ".to_string(),
+                    };
+                    let indent = format!(
+                        "{}{}",
+                        nested_indent,
+                        " ".repeat(marker.chars().count() * depth)
+                    );
+                    plain_text_lines(
+                        lines,
+                        &format!("{}{}", indent, marker),
+                        &format!("{}{}", indent, " ".repeat(marker.chars().count())),
+                        width,
+                        text,
+                    );
+                }
+            }
+            RoffBlock::Code(lines) => {
+                for line in lines {
+                    text.push_str(format!("{}{}", nested_indent, line).trim_end());
+                    text.push('\n');
+                }
+            }
+            RoffBlock::Table(rows) => {
+                let rows: Vec<Vec<String>> = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| plain_text_inline(cell, false)).collect())
+                    .collect();
+                let mut widths: Vec<usize> = vec![];
+                for row in &rows {
+                    for (i, cell) in row.iter().enumerate() {
+                        let width = cell.chars().count();
+                        match widths.get_mut(i) {
+                            Some(w) => *w = std::cmp::max(*w, width),
+                            None => widths.push(width),
+                        }
+                    }
+                }
+                for (i, row) in rows.iter().enumerate() {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                        .collect();
+                    text.push_str(&format!("{}{}\n", nested_indent, cells.join("  ").trim_end()));
+                    if i == 0 {
+                        let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+                        text.push_str(&format!("{}{}\n", nested_indent, rule.join("  ")));
+                    }
+                }
+            }
+            RoffBlock::Term { term, blocks } => {
+                text.push_str(&format!("{}{}\n", nested_indent, plain_text_inline(term, false)));
+                plain_text_from_roff_blocks(blocks, &nested_indent, width, text);
+            }
+        }
+    }
+}
+
+fn plain_text_lines(
+    lines: &[RoffLine],
+    first_indent: &str,
+    indent: &str,
+    width: usize,
+    text: &mut String,
+) {
+    // Text up to each line break is wrapped separately.
+    let mut filled = String::new();
+    let mut first = true;
+    for (i, (inlines, line_break)) in lines.iter().enumerate() {
+        if !filled.is_empty() {
+            filled.push(' ');
+        }
+        filled.push_str(&plain_text_inline(inlines, true));
+        if *line_break || i + 1 == lines.len() {
+            let line_indent = match first {
+                true => first_indent,
+                false => indent,
+            };
+            wrap_plain_text(&filled, line_indent, indent, width, text);
+            filled.clear();
+            first = false;
+        }
+    }
+}
+
+/// Wrap s to width, breaking lines at whitespace but not at NUL characters,
+/// which are shown as spaces.
+fn wrap_plain_text(s: &str, first_indent: &str, indent: &str, width: usize, text: &mut String) {
+    let mut line = first_indent.to_string();
+    let mut line_width = first_indent.chars().count();
+    let mut empty = true;
+    for word in s.split_whitespace() {
+        let word = word.replace('\0', " ");
+        let word_width = word.chars().count();
+        if !empty && line_width + 1 + word_width > width {
+            text.push_str(&line);
+            text.push('\n');
+            line = indent.to_string();
+            line_width = indent.chars().count();
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+            line_width += 1;
+        }
+        line.push_str(&word);
+        line_width += word_width;
+        empty = false;
+    }
+    text.push_str(line.trim_end());
+    text.push('\n');
+}
+
+/// Plain text for inline markup. If protect_monospace, spaces in monospace
+/// text are replaced with NUL characters so that it is not wrapped.
+fn plain_text_inline(inlines: &[RoffInline], protect_monospace: bool) -> String {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            RoffInline::Text(RoffFont::Monospace, text) if protect_monospace => {
+                text.replace(' ', "\0")
+            }
+            RoffInline::Text(_, text) => text.clone(),
+            RoffInline::Link { url, label } if url.trim_start_matches("mailto:") == label => {
+                format!("<{}>", label)
+            }
+            RoffInline::Link { url, label } => format!("{} <{}>", label, url),
+        })
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn genome_matches(args: &[&str]) -> clap::ArgMatches {
+        add_genome_specification_arguments(clap::Command::new("test"))
+            .try_get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+            .unwrap()
+    }
+
+    /// Write a small genome FASTA file, creating its directory if needed.
+    fn write_genome(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, ">contig1\nACGTACGT\n").unwrap();
+    }
+
+    fn file_names(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_genome_fasta_directory_recursive() {
+        let dir = tempfile::tempdir().unwrap();
+        write_genome(&dir.path().join("a.fna"));
+        write_genome(&dir.path().join("sub/b.fna"));
+        write_genome(&dir.path().join("sub/deeper/c.fna"));
+        let dir_str = dir.path().to_str().unwrap();
+
+        let paths = parse_list_of_genome_fasta_paths(&genome_matches(&["-d", dir_str]), true);
+        assert_eq!(vec!["a.fna"], file_names(&paths.unwrap()));
+
+        let paths = parse_list_of_genome_fasta_paths(
+            &genome_matches(&["-d", dir_str, "--genome-fasta-directory-recursive"]),
+            true,
+        );
+        assert_eq!(vec!["a.fna", "b.fna", "c.fna"], file_names(&paths.unwrap()));
+    }
+
+    #[test]
+    fn test_genome_fasta_extension_matches_whole_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.fna.gz", "b.fna", "c.gz", "d.fna.bak"] {
+            write_genome(&dir.path().join(name));
+        }
+        let dir_str = dir.path().to_str().unwrap();
+        for (extension, expected) in [
+            ("fna.gz", vec!["a.fna.gz"]),
+            (".fna.gz", vec!["a.fna.gz"]),
+            ("fna", vec!["b.fna"]),
+        ] {
+            let m = genome_matches(&["-d", dir_str, "-x", extension]);
+            let paths = parse_list_of_genome_fasta_paths(&m, true);
+            assert_eq!(expected, file_names(&paths.unwrap()), "extension {}", extension);
+        }
+    }
+
+    #[test]
+    fn test_genome_fasta_files_glob() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.fna", "a.fna", "c.fa"] {
+            write_genome(&dir.path().join(name));
+        }
+        let pattern = dir.path().join("*.fna");
+        let m = genome_matches(&["-f", pattern.to_str().unwrap()]);
+        let paths = parse_list_of_genome_fasta_paths(&m, true).unwrap();
+        assert_eq!(vec!["a.fna", "b.fna"], file_names(&paths));
+
+        let pattern = dir.path().join("*.fasta");
+        let m = genome_matches(&["-f", pattern.to_str().unwrap()]);
+        match parse_list_of_genome_fasta_paths(&m, true) {
+            Err(GenomeSpecificationError::UnmatchedGlobPattern { pattern: p }) => {
+                assert_eq!(pattern.to_str().unwrap(), p)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_genome_fasta_directory_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["m.fna", "b.fna", "z.fna", "a10.fna", "a2.fna"] {
+            write_genome(&dir.path().join(name));
+        }
+        let m = genome_matches(&["-d", dir.path().to_str().unwrap()]);
+        let files = parse_list_of_genome_fasta_files(&m, true).unwrap();
+        let expected: Vec<String> = ["a10.fna", "a2.fna", "b.fna", "m.fna", "z.fna"]
+            .iter()
+            .map(|name| dir.path().join(name).to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(expected, files);
+
+        // Files and lists keep the order the user gave
+        let m = genome_matches(&["-f", &expected[3], &expected[0]]);
+        let files = parse_list_of_genome_fasta_files(&m, true).unwrap();
+        assert_eq!(vec![expected[3].clone(), expected[0].clone()], files);
+    }
+
+    #[test]
+    fn test_repeated_genome_fasta_files_deduplicated() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.fna");
+        let b = dir.path().join("b.fna");
+        write_genome(&a);
+        write_genome(&b);
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+        // Within one flag, keeping the first of each
+        let m = genome_matches(&["-f", b, a, b, b]);
+        assert_eq!(vec![b, a], parse_list_of_genome_fasta_files(&m, true).unwrap());
+
+        // Across two sources
+        let list = dir.path().join("list.txt");
+        fs::write(&list, format!("{}\n", a)).unwrap();
+        let m = genome_matches(&["-f", a, &format!("@{}", list.display()), b]);
+        assert_eq!(vec![a, b], parse_list_of_genome_fasta_files(&m, true).unwrap());
+        let dir_str = dir.path().to_str().unwrap();
+        let m = genome_matches(&["-d", dir_str, "-d", dir_str]);
+        assert_eq!(vec![a, b], parse_list_of_genome_fasta_files(&m, true).unwrap());
+    }
+
+    #[test]
+    fn test_genome_fasta_list_relative_to_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        write_genome(&sub.join("a.fna"));
+        write_genome(&dir.path().join("b.fna"));
+        let absolute = dir.path().join("c.fna");
+        write_genome(&absolute);
+        let list = sub.join("genomes.txt");
+        fs::write(&list, format!("a.fna\n../b.fna\n{}\n", absolute.display())).unwrap();
+        let list_str = list.to_str().unwrap();
+
+        let m = genome_matches(&["--genome-fasta-list", list_str]);
+        assert_eq!(
+            vec!["a.fna", "../b.fna", absolute.to_str().unwrap()],
+            parse_list_of_genome_fasta_files(&m, true).unwrap()
+        );
+
+        let m = genome_matches(&[
+            "--genome-fasta-list",
+            list_str,
+            "--genome-fasta-list-relative-to-list",
+        ]);
+        assert_eq!(
+            vec![sub.join("a.fna"), sub.join("../b.fna"), absolute],
+            parse_list_of_genome_fasta_paths(&m, true).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_directory_given_to_genome_fasta_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let bins = dir.path().join("bins");
+        write_genome(&bins.join("b.fna"));
+        write_genome(&bins.join("a.fna"));
+        write_genome(&bins.join("notes.txt"));
+        let single = dir.path().join("c.fna");
+        write_genome(&single);
+        let m = genome_matches(&["-f", single.to_str().unwrap(), bins.to_str().unwrap()]);
+
+        assert_eq!(
+            vec![single, bins.join("a.fna"), bins.join("b.fna")],
+            parse_list_of_genome_fasta_paths(&m, true).unwrap()
+        );
+
+        let options = GenomeSpecificationOptions {
+            directories_in_files: DirectoryInFilesAction::Fail,
+            ..Default::default()
+        };
+        match parse_list_of_genome_fasta_paths_with_options(&m, &options) {
+            Err(e @ GenomeSpecificationError::DirectoryInGenomeFastaFiles { .. }) => {
+                assert!(e.to_string().contains("--genome-fasta-directory"), "{}", e)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_parse_genome_fasta_file_set_collapses_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.fna");
+        let b = dir.path().join("b.fna");
+        write_genome(&a);
+        write_genome(&b);
+        let (a_str, b_str) = (a.to_str().unwrap(), b.to_str().unwrap());
+        let m = genome_matches(&["-f", b_str, a_str, b_str, a_str]);
+
+        let set = parse_genome_fasta_file_set(&m, true).unwrap();
+        assert_eq!(vec![&a, &b], set.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_genome_fasta_files_response_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let response_file = dir.path().join("args.txt");
+        fs::write(&response_file, "b.fna\n\n  c.fna  \n").unwrap();
+        let response_arg = format!("@{}", response_file.display());
+        let m = genome_matches(&["-f", "a.fna", &response_arg, "@@d.fna", "e.fna"]);
+        assert_eq!(
+            vec!["a.fna", "b.fna", "c.fna", "@d.fna", "e.fna"],
+            parse_list_of_genome_fasta_files(&m, true).unwrap()
+        );
+
+        let missing = dir.path().join("missing.txt");
+        let m = genome_matches(&["-f", "a.fna", &format!("@{}", missing.display())]);
+        match parse_list_of_genome_fasta_paths(&m, true) {
+            Err(GenomeSpecificationError::UnreadableResponseFile { path, .. }) => {
+                assert_eq!(missing, path)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_two_prefixed_fasta_specifications() {
+        let dir = tempfile::tempdir().unwrap();
+        let references = dir.path().join("references");
+        write_genome(&references.join("r1.fa"));
+        write_genome(&references.join("r2.fna"));
+        let command = add_fasta_specification_arguments(
+            add_genome_specification_arguments(clap::Command::new("test")),
+            "reference",
+        );
+        let m = command
+            .try_get_matches_from([
+                "test",
+                "-f",
+                "a.fna",
+                "b.fna",
+                "--reference-fasta-directory",
+                references.to_str().unwrap(),
+                "--reference-fasta-extension",
+                "fa",
+            ])
+            .unwrap();
+
+        assert_eq!(
+            vec!["a.fna", "b.fna"],
+            parse_list_of_genome_fasta_files(&m, true).unwrap()
+        );
+        assert_eq!(
+            vec![references.join("r1.fa").to_str().unwrap()],
+            parse_list_of_fasta_files(&m, "reference", true).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_genome_spec_args_config_default_extension() {
+        let config = GenomeSpecArgsConfig {
+            default_extension: Some("fasta".to_string()),
+            extension_help: Some("File extension of genomes.".to_string()),
+            ..Default::default()
+        };
+        let command =
+            add_genome_specification_arguments_with_config(clap::Command::new("test"), &config);
+        let m = command.clone().try_get_matches_from(["test"]).unwrap();
+        assert_eq!(
+            Some(&"fasta".to_string()),
+            m.get_one::<String>("genome-fasta-extension")
+        );
+
+        // The default is used when searching a directory
+        let dir = tempfile::tempdir().unwrap();
+        write_genome(&dir.path().join("a.fasta"));
+        write_genome(&dir.path().join("b.fna"));
+        let m = command
+            .try_get_matches_from(["test", "-d", dir.path().to_str().unwrap()])
+            .unwrap();
+        let paths = parse_list_of_genome_fasta_paths(&m, true).unwrap();
+        assert_eq!(vec!["a.fasta"], file_names(&paths));
+
+        let section = add_genome_specification_to_section_with_config(Section::new("G"), &config);
+        let roff = Manual::new("test").custom(section).render();
+        let help = "File extension of genomes. [default: \\f[C]fasta\\f[R]]";
+        assert!(roff.contains(help), "{}", roff);
+        assert!(!roff.contains("\\f[C]fna\\f[R]"), "{}", roff);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_list_of_genome_fasta_paths_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let non_utf8 = dir.path().join(OsStr::from_bytes(b"\xff.fna"));
+        write_genome(&non_utf8);
+        write_genome(&dir.path().join("a.fna"));
+        let m = genome_matches(&["-d", dir.path().to_str().unwrap()]);
+        let paths = parse_list_of_genome_fasta_paths(&m, true).unwrap();
+        assert_eq!(vec![dir.path().join("a.fna"), non_utf8], paths);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_parse_list_of_genome_fasta_files_skips_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let non_utf8 = dir.path().join(OsStr::from_bytes(b"\xff.fna"));
+        write_genome(&non_utf8);
+        write_genome(&dir.path().join("a.fna"));
+        let m = genome_matches(&["-d", dir.path().to_str().unwrap()]);
+
+        let paths = parse_list_of_genome_fasta_paths(&m, true).unwrap();
+        assert!(paths.contains(&non_utf8));
+        let files = parse_list_of_genome_fasta_files(&m, true).unwrap();
+        assert_eq!(
+            vec![dir.path().join("a.fna").to_str().unwrap().to_string()],
+            files
+        );
+    }
+
+    #[test]
+    fn test_genome_fasta_archive_needs_parsed_genome_fasta_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let genome = dir.path().join("a.fna");
+        write_genome(&genome);
+        let archive = dir.path().join("genomes.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        builder.append_path_with_name(&genome, "genomes/a.fna").unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+        let m = genome_matches(&["--genome-fasta-archive", archive.to_str().unwrap()]);
+
+        match parse_list_of_genome_fasta_paths(&m, true) {
+            Err(GenomeSpecificationError::TemporaryFilesNotSupported { specification }) => {
+                assert_eq!("--genome-fasta-archive", specification)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let parsed = parse_genome_fasta_files_with_options(&m, &Default::default()).unwrap();
+        assert_eq!(1, parsed.files.len());
+        let extracted = parsed.files[0].path.clone();
+        assert!(extracted.exists());
+        drop(parsed);
+        assert!(!extracted.exists());
+    }
+
+    #[test]
+    fn test_genome_specification_arguments_required_error_kinds() {
+        let error_kind = |args: &[&str]| {
+            add_genome_specification_arguments_required(clap::Command::new("test"))
+                .try_get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+                .unwrap_err()
+                .kind()
+        };
+        assert_eq!(
+            clap::error::ErrorKind::ArgumentConflict,
+            error_kind(&["-f", "a.fna", "-d", "genomes"])
+        );
+        assert_eq!(
+            clap::error::ErrorKind::ArgumentConflict,
+            error_kind(&["--genome-fasta-list", "list.txt", "-d", "genomes"])
+        );
+        assert_eq!(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            error_kind(&[])
+        );
+        assert!(
+            add_genome_specification_arguments_required(clap::Command::new("test"))
+                .try_get_matches_from(["test", "-d", "genomes"])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_verbosity_flag_counts() {
+        let level = |args: &[&str]| {
+            let m = add_clap_verbosity_flags(clap::Command::new("test"))
+                .try_get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+                .unwrap();
+            let verbose = m.get_count("verbose");
+            assert_eq!(verbose, flag_count(&m, "verbose"));
+            select_log_level(None, flag_count(&m, "quiet"), verbose, None).unwrap().0
+        };
+        assert_eq!(LevelFilter::Info, level(&[]));
+        assert_eq!(LevelFilter::Debug, level(&["-v"]));
+        assert_eq!(LevelFilter::Trace, level(&["-vv"]));
+        assert_eq!(LevelFilter::Trace, level(&["-v", "--verbose", "-v"]));
+        assert_eq!(LevelFilter::Error, level(&["--quiet"]));
+        assert_eq!(LevelFilter::Off, level(&["--quiet", "--quiet"]));
+    }
+
+    #[test]
+    fn test_log_level_precedence() {
+        let _env_lock = crate::TEST_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let variable = "BIRD_TOOL_UTILS_TEST_LOG_LEVEL";
+        let level = |args: &[&str], log_level_env_var: Option<&str>| {
+            let m = add_clap_verbosity_flags(clap::Command::new("test"))
+                .try_get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+                .unwrap();
+            let log_level_flag = m.get_one::<String>("log-level").map(|l| l.parse().unwrap());
+            let (verbose, quiet) = (m.get_count("verbose"), m.get_count("quiet"));
+            select_log_level(log_level_flag, quiet, verbose, log_level_env_var).unwrap()
+        };
+
+        env::remove_var(variable);
+        assert_eq!((LevelFilter::Info, "by default".to_string()), level(&[], Some(variable)));
+
+        env::set_var(variable, "trace");
+        let from_env = (LevelFilter::Trace, format!("as set by {}", variable));
+        assert_eq!(from_env, level(&[], Some(variable)));
+        assert_eq!(LevelFilter::Info, level(&[], None).0);
+        assert_eq!(LevelFilter::Debug, level(&["-v"], Some(variable)).0);
+        assert_eq!(LevelFilter::Error, level(&["--quiet"], Some(variable)).0);
+        assert_eq!(
+            (LevelFilter::Warn, "as set by --log-level".to_string()),
+            level(&["--log-level", "warn"], Some(variable))
+        );
+        env::remove_var(variable);
+    }
+
+    #[test]
+    fn test_verbose_and_quiet_conflict() {
+        for command in [
+            add_clap_verbosity_flags(clap::Command::new("test")),
+            add_clap_logging_flags(clap::Command::new("test")),
+        ] {
+            let error = command.try_get_matches_from(["test", "-v", "--quiet"]).unwrap_err();
+            assert_eq!(clap::error::ErrorKind::ArgumentConflict, error.kind());
+            let message = error.to_string();
+            assert!(message.contains("--verbose") && message.contains("--quiet"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_try_set_log_level_invalid_env_log_level() {
+        let _env_lock = crate::TEST_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let variable = "BIRD_TOOL_UTILS_TEST_INVALID_LOG_LEVEL";
+        env::set_var(variable, "loud");
+        let m = add_clap_verbosity_flags(clap::Command::new("test"))
+            .try_get_matches_from(["test"])
+            .unwrap();
+        let options = LoggingOptions {
+            log_level_env_var: Some(variable.to_string()),
+            ..Default::default()
+        };
+        match try_set_log_level_with_options(&m, true, "test", "0.1", &options) {
+            Err(SetLogLevelError::InvalidLogLevel { variable: v, value }) => {
+                assert_eq!(variable, v);
+                assert_eq!("loud", value);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+        env::remove_var(variable);
+    }
+
+    /// Write an executable 'man' script to dir, which records its arguments,
+    /// MAN_KEEP_FORMATTING and the page it was given in log, and then exits
+    /// with status.
+    #[cfg(unix)]
+    fn write_fake_man(dir: &Path, log: &Path, status: i32) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = dir.join("man");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\n\
+                echo \"$@\" > '{log}'\n\
+                echo \"MAN_KEEP_FORMATTING=$MAN_KEEP_FORMATTING\" >> '{log}'\n\
+                for page; do :; done\n\
+                cat \"$page\" >> '{log}'\n\
+                if [ \"$1\" = -l ]; then cat \"$page\"; fi\n\
+                exit {status}\n",
+                log = log.display(),
+                status = status
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_display_roff_with_fake_man() {
+        let dir = tempfile::tempdir().unwrap();
+        let working_man = dir.path().join("working");
+        let failing_man = dir.path().join("failing");
+        fs::create_dir(&working_man).unwrap();
+        fs::create_dir(&failing_man).unwrap();
+        let log = dir.path().join("man.log");
+        write_fake_man(&working_man, &log, 0);
+        write_fake_man(&failing_man, &log, 1);
+        // 'man' is found in man_dir, ahead of the PATH of the process, which
+        // is left unchanged as other tests run in parallel.
+        let original_path = env::var_os("PATH").unwrap_or_default();
+        let full_help_env = |man_dir: &Path, pagers: Vec<(&'static str, String)>| FullHelpEnv {
+            path: Some(
+                env::join_paths(
+                    std::iter::once(man_dir.to_path_buf())
+                        .chain(env::split_paths(&original_path)),
+                )
+                .unwrap(),
+            ),
+            pagers,
+        };
+        let roff = ".TH TEST 1\n.SH NAME\ntest\n";
+        // The page is the last of the arguments, which are on the first line
+        let page_given = |log: &str| {
+            PathBuf::from(log.lines().next().unwrap().rsplit(' ').next().unwrap())
+        };
+
+        assert_eq!(Ok(true), display_roff_with_man(roff, &full_help_env(&working_man, vec![])));
+        let logged = fs::read_to_string(&log).unwrap();
+        assert!(logged.ends_with(roff));
+        assert!(!logged.starts_with("-l "));
+        assert!(!page_given(&logged).exists());
+
+        let paged = dir.path().join("paged");
+        let pagers = vec![("MANPAGER", format!("cat > '{}'", paged.display()))];
+        assert_eq!(Ok(true), display_roff_with_man(roff, &full_help_env(&working_man, pagers)));
+        let logged = fs::read_to_string(&log).unwrap();
+        assert!(logged.starts_with("-l "));
+        assert!(logged.contains("MAN_KEEP_FORMATTING=1\n"));
+        assert!(!page_given(&logged).exists());
+        assert_eq!(roff, fs::read_to_string(&paged).unwrap());
+
+        assert_eq!(Ok(false), display_roff_with_man(roff, &full_help_env(&failing_man, vec![])));
+        assert!(!page_given(&fs::read_to_string(&log).unwrap()).exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_display_full_help_without_man() {
+        // A PATH with only what the pager needs
+        let dir = tempfile::tempdir().unwrap();
+        for program in ["sh", "cat"] {
+            let target = which::which(program).unwrap();
+            std::os::unix::fs::symlink(target, dir.path().join(program)).unwrap();
+        }
+        let paged = dir.path().join("paged");
+        let full_help_env = FullHelpEnv {
+            path: Some(dir.path().as_os_str().to_owned()),
+            pagers: vec![("MANPAGER", format!("cat > '{}'", paged.display()))],
+        };
+        let roff = ".TH TEST 1\n.SH NAME\ntest \\- do things\n";
+
+        assert_eq!(Ok(false), display_roff_with_man(roff, &full_help_env));
+        assert!(!paged.exists());
+        // Shown as plain text with the pager instead
+        let mut out = Vec::new();
+        assert_eq!(Ok(()), display_full_help_roff_with_env(roff, &mut out, &full_help_env));
+        assert!(out.is_empty());
+        assert_eq!(
+            plain_text_from_roff(roff, full_help_width()),
+            fs::read_to_string(&paged).unwrap()
+        );
+        assert!(fs::read_to_string(&paged).unwrap().contains("test - do things"));
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_roff_helpers_validate() {
+        // Text which needs escaping, e.g. lines starting with '.' or '\''
+        let awkward = ".hidden 'quoted' back\\slash -dash";
+        let rows: &[&[&str]] = &[&["Column", "Other"], &[awkward, "b"], &["c", "d"]];
+        let table_options = TableOptions {
+            has_header: true,
+            alignments: vec![TableAlignment::Left, TableAlignment::Right],
+            widths: vec![Some(10), None],
+            wrap_columns: Some(vec![0]),
+        };
+        let paragraphs = vec![
+            ("escape_roff", escape_roff(awkward)),
+            ("default_roff", default_roff(awkward)),
+            ("default_none_roff", default_none_roff()),
+            ("default_list_roff", default_list_roff(&["fna", awkward])),
+            ("default_option_roff", default_option_roff(Some(awkward))),
+            ("monospace_roff", monospace_roff(awkward)),
+            (
+                "monospace_roff_multiline",
+                monospace_roff_multiline(&format!("{}\n{}", awkward, awkward)),
+            ),
+            ("bold_roff", bold_roff(awkward)),
+            ("italic_roff", italic_roff(awkward)),
+            ("bold_monospace_roff", bold_monospace_roff(awkward)),
+            ("list_roff", list_roff(&[awkward, "b"])),
+            (
+                "list_roff_nested",
+                list_roff_nested(&[
+                    ListItem::Text(awkward.to_string()),
+                    ListItem::SubList(vec![ListItem::Text(awkward.to_string())]),
+                ]),
+            ),
+            ("numbered_list_roff", numbered_list_roff(&[awkward, "b"])),
+            ("definition_list_roff", definition_list_roff(&[(awkward, awkward)])),
+            (
+                "definition_list_roff_formatted",
+                definition_list_roff_formatted(&[(&bold_roff("a"), &italic_roff("b"))]),
+            ),
+            ("url_roff", url_roff("https://example.com/a-b", Some(awkward))),
+            ("mailto_roff", mailto_roff("a@example.com", None)),
+            ("code_block_roff", code_block_roff(&[awkward, "./run.sh"])),
+            ("list_roff_unescaped", list_roff_unescaped(&[&bold_roff("a")])),
+            ("table_roff", table_roff(rows)),
+            ("table_roff_unescaped", table_roff_unescaped(&[&[&bold_roff("a")]])),
+            ("table_roff_options", table_roff_options(rows, &table_options)),
+            (
+                "table_roff_options_unescaped",
+                table_roff_options_unescaped(&[&[&bold_roff("a")]], &table_options),
+            ),
+        ];
+        let sections = vec![
+            ("environment_section", environment_section(&[("VAR", awkward)])),
+            (
+                "citation_section",
+                citation_section(&[awkward, "Smith et al. doi:10.1038/s41587-021-01011-1"]),
+            ),
+            ("see_also_section", see_also_section(&[("samtools", 1)])),
+            ("example_section", example_section(&[(awkward, "tool -f ./a.fna")])),
+            ("examples", examples().example(awkward, "tool -f ./a.fna").section()),
+            (
+                "add_genome_specification_to_section",
+                add_genome_specification_to_section(Section::new("Genomes")),
+            ),
+        ];
+        let skeleton = || manual_skeleton("tool", "sub", "About", "1.0", "A <a@example.com>", "");
+
+        for (name, roff) in paragraphs {
+            let manual = skeleton().custom(Section::new("Test").paragraph(&roff));
+            if let Err(e) = validate_roff(&manual.render()) {
+                panic!("Invalid roff from {}: {}", name, e)
+            }
+        }
+        for (name, section) in sections {
+            if let Err(e) = validate_roff(&skeleton().custom(section).render()) {
+                panic!("Invalid roff from {}: {}", name, e)
+            }
+        }
+    }
+
+    #[test]
+    fn test_opts_from_command_matches_genome_specification_section() {
+        // The flags and value names of each entry, and whether it is noted as
+        // hidden, as the help of each differs.
+        let entries = |section: Section| -> Vec<(String, bool)> {
+            let roff = Manual::new("test").custom(section).render();
+            let lines: Vec<&str> = roff.lines().collect();
+            lines
+                .windows(3)
+                // Leaving out the EXIT STATUS section
+                .filter(|window| window[0] == ".TP" && window[1].starts_with("\\fB\\-"))
+                .map(|window| {
+                    (
+                        window[1].to_string(),
+                        window[2].ends_with("Not shown by \\-\\-help."),
+                    )
+                })
+                .collect()
+        };
+        let command = add_genome_specification_arguments(clap::Command::new("test"));
+        let (args, positionals) = opts_from_command(&command, true);
+        assert!(positionals.is_empty());
+        let from_command = entries(
+            args.into_iter()
+                .fold(Section::new("Genomes"), |section, arg| arg.add_to_section(section)),
+        );
+        let from_options = entries(add_genome_specification_to_section(Section::new("Genomes")));
+        assert_eq!(GENOME_SPEC_OPTIONS.len(), from_options.len());
+        assert!(from_options.iter().any(|(_, hidden)| *hidden));
+        assert_eq!(from_options, from_command);
+    }
+
+    #[test]
+    fn test_genome_spec_options_have_arg_and_man_entry() {
+        let command = add_genome_specification_arguments(clap::Command::new("test"));
+        let section = add_genome_specification_to_section(Section::new("GENOMES"));
+        let manual = Manual::new("test").custom(section);
+        let text = plain_text_from_roff(&manual.render(), 80);
+        let headings: Vec<&str> = text.lines().map(|line| line.trim()).collect();
+
+        for option in GENOME_SPEC_OPTIONS {
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == option.name)
+                .unwrap_or_else(|| panic!("No Arg for {}", option.name));
+            assert_eq!(Some(option.name), arg.get_long());
+            assert_eq!(option.short, arg.get_short());
+            assert_eq!(option.value_name.is_some(), arg.get_action().takes_values());
+            assert_eq!(option.hidden, arg.is_hide_set());
+
+            let mut heading = format!("--{}", option.name);
+            if let Some(short) = option.short {
+                heading = format!("-{}, {}", short, heading);
+            }
+            if let Some(value_name) = option.value_name {
+                heading = format!("{} {}", heading, value_name);
+                if option.multiple_values {
+                    heading.push_str(" ..");
+                }
+            }
+            assert!(
+                headings.contains(&heading.as_str()),
+                "No man page entry '{}' in:\n{}",
+                heading,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_full_help_notes_hidden_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = add_generate_man_page_flag(add_genome_specification_arguments(
+            clap::Command::new("tool").args([
+                clap::Arg::new("full-help").long("full-help").action(clap::ArgAction::SetTrue),
+                clap::Arg::new("full-help-roff")
+                    .long("full-help-roff")
+                    .action(clap::ArgAction::SetTrue),
+            ]),
+        ));
+        let write_man_page = |name: &str, section: Section| {
+            let path = dir.path().join(name);
+            let m = command
+                .clone()
+                .try_get_matches_from(["tool", "--generate-man-page", path.to_str().unwrap()])
+                .unwrap();
+            let manual = manual_skeleton("tool", "", "About", "1.0", "A <a@example.com>", "")
+                .custom(section);
+            assert_eq!(Ok(true), print_full_help_if_needed_noexit(&m, manual));
+            std::fs::read_to_string(path).unwrap()
+        };
+        let notes = ".SH NOTES\nSome options are not shown by \\-\\-help, and are marked 'Not shown by \\-\\-help.' above.\n";
+
+        // --max-genomes is hidden
+        let section = add_genome_specification_to_section(Section::new("G"));
+        let roff = write_man_page("hidden.1", section);
+        assert!(roff.contains(notes), "No notes in:\n{}", roff);
+        assert!(roff.find(notes).unwrap() < roff.find(".SH EXIT STATUS").unwrap());
+        #[cfg(feature = "testing")]
+        validate_roff(&roff).unwrap();
+
+        let roff = write_man_page("none.1", Section::new("G").paragraph("Nothing hidden."));
+        assert!(!roff.contains(".SH NOTES"));
+    }
+
+    /// A small manual with flags, options, and help using the roff helpers
+    /// which render_manual_markdown(), render_manual_html() and
+    /// render_manual_plaintext() convert.
+    fn snapshot_manual() -> Manual {
+        let nested = list_roff_nested(&[
+            ListItem::Text("dense".to_string()),
+            ListItem::SubList(vec![
+                ListItem::Text("genome".to_string()),
+                ListItem::Text("count".to_string()),
+            ]),
+            ListItem::Text("sparse".to_string()),
+        ]);
+        let table = table_roff(&[
+            &["Column", "Meaning"],
+            &["genome", "Genome name"],
+            &["count", "Read count"],
+        ]);
+        let docs = url_roff("https://example.com/docs?a=1&b=2", Some("the \"docs\""));
+        let notes = format!(
+            "Genomes <named> A & B are compared with {} which keeps going for a while longer than \
+             the width.",
+            monospace_roff("tool sub --output out.tsv")
+        );
+        Manual::new("tool-sub")
+            .about("Do things to genomes")
+            .flag(Flag::new().short("-q").long("--quiet").help("Print less"))
+            .option(Opt::new("PATH").short("-o").long("--output").help(&format!(
+                "Write output here. {}",
+                default_roff("out.tsv")
+            )))
+            .custom(
+                Section::new("Formats")
+                    .paragraph(&format!("Formats are {} or:", monospace_roff("tsv")))
+                    .paragraph(&nested)
+                    .paragraph(&table)
+                    .paragraph(&code_block_roff(&["tool sub -o out.tsv", "  --quiet"]))
+                    .paragraph(&format!("Documentation is at{}", docs))
+                    .paragraph(&url_roff("https://example.com", None)),
+            )
+            .custom(
+                Section::new("Notes")
+                    .paragraph(&notes)
+                    .paragraph("/a/very/long/path/to/a/genome/file/which/does/not/fit/anywhere.fna"),
+            )
+    }
+
+    #[test]
+    fn test_render_manual_markdown_snapshot() {
+        let expected = r#"# tool-sub(1)
+
+## Name
+
+tool-sub - Do things to genomes
+
+## Synopsis
+
+**tool-sub** [FLAGS] [OPTIONS]
+
+## Flags
+
+- `-q`, `--quiet`
+
+  Print less
+
+## Options
+
+- `-o`, `--output` *PATH*
+
+  Write output here. [default: `out.tsv`]
+
+## Formats
+
+Formats are `tsv` or:
+
+- dense
+  - genome
+  - count
+- sparse
+
+| Column | Meaning |
+| --- | --- |
+| genome | Genome name |
+| count | Read count |
+
+```
+tool sub -o out.tsv
+  --quiet
+```
+
+Documentation is at
+[the "docs"](https://example.com/docs?a=1&b=2)
+
+<https://example.com>
+
+## Notes
+
+Genomes \<named> A & B are compared with `tool sub --output out.tsv` which keeps going for a while longer than the width.
+
+/a/very/long/path/to/a/genome/file/which/does/not/fit/anywhere.fna
+
+## Exit status
+
+- `0`
+
+  Successful program execution.
+
+- `1`
+
+  Unsuccessful program execution.
+
+- `101`
+
+  The program panicked.
+"#;
+        assert_eq!(expected, render_manual_markdown(snapshot_manual()));
+    }
+
+    #[test]
+    fn test_parse_manual_roff() {
+        let roff = format!(
+            ".TP\n\\fB\\-o\\fR \\fIPATH\\fR\nWrite {}.\n{}{}{}",
+            monospace_roff("out"),
+            list_roff_nested(&[
+                ListItem::Text("a".to_string()),
+                ListItem::SubList(vec![ListItem::Text("b".to_string())]),
+            ]),
+            table_roff(&[&["x", "y"]]),
+            url_roff("https://example.com", Some("e"))
+        );
+        let text = |font, s: &str| RoffInline::Text(font, s.to_string());
+        let line = |s: &str| (vec![text(RoffFont::Regular, s)], false);
+        assert_eq!(
+            vec![
+                RoffBlock::Term {
+                    term: vec![
+                        text(RoffFont::Bold, "-o"),
+                        text(RoffFont::Regular, " "),
+                        text(RoffFont::Italic, "PATH"),
+                    ],
+                    blocks: vec![
+                        RoffBlock::Paragraph(vec![(
+                            vec![
+                                text(RoffFont::Regular, "Write "),
+                                text(RoffFont::Monospace, "out"),
+                                text(RoffFont::Regular, "."),
+                            ],
+                            false
+                        )]),
+                        RoffBlock::List {
+                            numbered: false,
+                            items: vec![(0, vec![line("a")]), (1, vec![line("b")])],
+                        },
+                    ],
+                },
+                RoffBlock::Table(vec![vec![
+                    vec![text(RoffFont::Regular, "x")],
+                    vec![text(RoffFont::Regular, "y")],
+                ]]),
+                RoffBlock::Paragraph(vec![(
+                    vec![RoffInline::Link {
+                        url: "https://example.com".to_string(),
+                        label: "e".to_string(),
+                    }],
+                    false
+                )]),
+            ],
+            parse_manual_roff(&roff)
+        );
+    }
+
+    /// Check that each element of html is closed, in the right order, and
+    /// that '<', '>' and '&' only appear in tags and entities, panicking if not.
+    fn assert_balanced_html(html: &str) {
+        let mut open: Vec<&str> = vec![];
+        let mut rest = html.strip_prefix("<!DOCTYPE html>\n").unwrap();
+        while let Some(start) = rest.find('<') {
+            let text = &rest[..start];
+            assert!(!text.contains('>'), "Unescaped '>' in {:?}", text);
+            for (i, _) in text.match_indices('&') {
+                let entity = &text[i..text[i..].find(';').map_or(text.len(), |end| i + end + 1)];
+                assert!(
+                    ["&amp;", "&lt;", "&gt;", "&quot;"].contains(&entity),
+                    "Unescaped '&' in {:?}",
+                    text
+                );
+            }
+            let end = start + rest[start..].find('>').expect("Unterminated tag");
+            let tag = &rest[start + 1..end];
+            let name = tag.split(' ').next().unwrap();
+            match name.strip_prefix('/') {
+                Some(name) => assert_eq!(open.pop(), Some(name), "Unbalanced </{}>", name),
+                None if name == "meta" => {}
+                None => open.push(name),
+            }
+            rest = &rest[end + 1..];
+        }
+        assert_eq!("\n", rest);
+        assert!(open.is_empty(), "Unclosed {:?}", open);
+    }
+
+    #[test]
+    fn test_render_manual_html_well_formed() {
+        let html = render_manual_html(snapshot_manual());
+        assert_balanced_html(&html);
+        assert!(html.contains("<p>Genomes &lt;named&gt; A &amp; B are compared with <code>"));
+        assert!(html.contains(
+            "<a href=\"https://example.com/docs?a=1&amp;b=2\">the &quot;docs&quot;</a>"
+        ));
+        assert!(html.contains("<li>dense\n<ul>\n<li>genome</li>\n<li>count</li>\n</ul>\n</li>"));
+        assert!(html.contains(
+            "<tr>\n<td><code>-o</code>, <code>--output</code> <i>PATH</i></td>\n<td>\n"
+        ));
+
+        // A list ending two levels deep, within the description of a term
+        let nested = list_roff_nested(&[
+            ListItem::Text("a".to_string()),
+            ListItem::SubList(vec![
+                ListItem::Text("b".to_string()),
+                ListItem::SubList(vec![ListItem::Text("c <&> \"d\"".to_string())]),
+            ]),
+        ]);
+        let roff = format!(
+            ".TH T 1\n.SH S\n{}\n.TP\n\\fB\\-\\-format\\fR\n{}",
+            list_roff(&["e"]),
+            nested
+        );
+        let html = html_from_roff(&roff);
+        assert_balanced_html(&html);
+        assert!(html.contains(concat!(
+            "<td><code>--format</code></td>\n<td>\n",
+            "<ul>\n<li>a\n<ul>\n<li>b\n<ul>\n<li>c &lt;&amp;&gt; &quot;d&quot;</li>\n",
+            "</ul>\n</li>\n</ul>\n</li>\n</ul>\n</td>\n</tr>\n</table>\n"
+        )));
+    }
+
+    #[test]
+    fn test_font_roff() {
+        assert_eq!("\\f[B]--output\\f[R]", bold_roff("--output"));
+        assert_eq!("\\f[I]PATH\\f[R]", italic_roff("PATH"));
+        assert_eq!("\\f[CB]-o\\f[R]", bold_monospace_roff("-o"));
+        assert_eq!("\\f[C]a.fna\\f[R]", monospace_roff("a.fna"));
+        // Font changes in the input are shown rather than applied
+        assert_eq!("\\f[B]a\\ef[Ib\\f[R]", bold_roff("a\\f[Ib"));
+        assert_eq!("\\f[I]\\efBPATH\\f[R]", italic_roff("\\fBPATH"));
+        assert_eq!("\\f[CB]\\&.hidden\\f[R]", bold_monospace_roff(".hidden"));
+    }
+
+    #[test]
+    fn test_escape_roff() {
+        assert_eq!("\\&./configure", escape_roff("./configure"));
+        assert_eq!("\\&'quoted' text", escape_roff("'quoted' text"));
+        assert_eq!("a\n\\&.b\n\\&'c", escape_roff("a\n.b\n'c"));
+        assert_eq!("a.b it's", escape_roff("a.b it's"));
+        assert_eq!("\\efBbold\\efR", escape_roff("\\fBbold\\fR"));
+        assert_eq!("say \"hi\"", escape_roff("say \"hi\""));
+        assert_eq!("--genome-fasta-files", escape_roff("--genome-fasta-files"));
+
+        // Hyphens are escaped when the Manual is rendered.
+        let paragraph = escape_roff(".a --b \"c\" \\fB");
+        let roff = Manual::new("t").custom(Section::new("S").paragraph(&paragraph)).render();
+        assert!(roff.contains("\n\\&.a \\-\\-b \"c\" \\efB\n"), "{}", roff);
+    }
+
+    #[test]
+    fn test_table_roff_options_alignment() {
+        let rows: &[&[&str]] = &[&["Genome", "Reads", "Status"], &["a.fna", "10", "ok"]];
+        let options = TableOptions {
+            has_header: true,
+            alignments: vec![TableAlignment::Left, TableAlignment::Right, TableAlignment::Center],
+            widths: vec![None, Some(8)],
+            ..Default::default()
+        };
+        assert_eq!(
+            "\n.TS\ntab(@);\nlb rbw(8n) cb \nl rw(8n) c .\n\
+             T{\nGenome\nT}@T{\nReads\nT}@T{\nStatus\nT}\n_\n\
+             T{\na.fna\nT}@T{\n10\nT}@T{\nok\nT}\n.TE\n",
+            table_roff_options(rows, &options)
+        );
+
+        // As table_roff() has always made them, with the first row underlined
+        // but not bold.
+        let expected = "\n.TS\ntab(@);\nl l l .\n\
+             T{\nGenome\nT}@T{\nReads\nT}@T{\nStatus\nT}\n_\n\
+             T{\na.fna\nT}@T{\n10\nT}@T{\nok\nT}\n.TE\n";
+        assert!(!TableOptions::default().has_header);
+        assert_eq!(expected, table_roff(rows));
+        assert_eq!(expected, table_roff_options(rows, &TableOptions::default()));
+    }
+
+    #[test]
+    fn test_table_roff_ragged_single_row_and_empty() {
+        assert_eq!(
+            "\n.TS\ntab(@);\nl l l .\n\
+             T{\na\nT}@T{\nb\nT}@T{\nc\nT}\n_\n\
+             T{\nd\nT}@T{\nT}@T{\nT}\n\
+             T{\nT}@T{\nT}@T{\nT}\n.TE\n",
+            table_roff(&[&["a", "b", "c"], &["d"], &[]])
+        );
+        // Short rows before the longest
+        assert_eq!(
+            "\n.TS\ntab(@);\nl l .\nT{\na\nT}@T{\nT}\n_\nT{\nb\nT}@T{\nc\nT}\n.TE\n",
+            table_roff(&[&["a"], &["b", "c"]])
+        );
+        assert_eq!(
+            "\n.TS\ntab(@);\nl l .\nT{\nonly\nT}@T{\nrow\nT}\n_\n.TE\n",
+            table_roff(&[&["only", "row"]])
+        );
+        assert_eq!("", table_roff(&[]));
+        assert_eq!("", table_roff(&[&[], &[]]));
+    }
+
+    #[test]
+    fn test_numbered_list_roff() {
+        assert_eq!(
+            "\n.IP 1. 3\nDownload the genomes\n\
+             .IP 2. 3\nRun the tool on them:\n.br\n\\&./run.sh genomes\n\
+             .IP 3. 3\nCheck the output\n.PP\n",
+            numbered_list_roff(&[
+                "Download the genomes",
+                "Run the tool on them:\n./run.sh genomes",
+                "Check the output"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_definition_list_roff() {
+        assert_eq!(
+            "\n.TP\n\\f[B]0\\f[R]\nSuccess. Results were written.\n\
+             .TP\n\\f[B]2\\f[R]\nA genome could not be read.\n\\&.fna files must be FASTA.\n.PP\n",
+            definition_list_roff(&[
+                ("0", "Success. Results were written."),
+                ("2", "A genome could not be read.\n.fna files must be FASTA."),
+            ])
+        );
+        assert_eq!(
+            "\n.TP\n\\f[C]genome\\f[R]\nName of the \\efBgenome\n.PP\n",
+            definition_list_roff_formatted(&[(&monospace_roff("genome"), "Name of the \\fBgenome")])
+        );
+    }
+
+    #[test]
+    fn test_url_roff() {
+        assert_eq!(
+            "\n.UR https://example.com/a%20b?c=d\nThe \\efBdocs\n.UE\n",
+            url_roff("https://example.com/a b?c=d", Some("The \\fBdocs"))
+        );
+        assert_eq!("\n.UR https://example.com\n.UE\n", url_roff("https://example.com", None));
+        assert_eq!(
+            "\n.MT a@example.com\nA. Person\n.ME\n",
+            mailto_roff("a@example.com", Some("A. Person"))
+        );
+        assert_eq!("\n.MT a@example.com\n.ME\n", mailto_roff("a@example.com", None));
+    }
+
+    #[test]
+    fn test_write_manual_to_file_round_trip() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let manual = || Manual::new("tool-sub").about("Do things").custom(Section::new("S"));
+        let roff = manual().render();
+
+        let path = dir.path().join("man1/tool-sub.1");
+        write_manual_to_file(manual(), &path, false).unwrap();
+        assert_eq!(roff, fs::read_to_string(&path).unwrap());
+
+        let path = dir.path().join("tool-sub.1.gz");
+        write_manual_to_file(manual(), &path, true).unwrap();
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(fs::File::open(&path).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(roff, decompressed);
+
+        // The roff can be written again once rendered
+        let path = dir.path().join("again.1");
+        write_roff_to_file(&roff, &path, false).unwrap();
+        assert_eq!(roff, fs::read_to_string(&path).unwrap());
+        // No temporary files are left behind
+        let mut entries: Vec<PathBuf> =
+            fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().path()).collect();
+        entries.sort();
+        assert_eq!(vec!["again.1", "man1", "tool-sub.1.gz"], file_names(&entries));
+    }
+
+    #[test]
+    fn test_render_manual_plaintext_snapshot() {
+        let expected_40 = r#"TOOL-SUB(1)
+
+NAME
+    tool-sub - Do things to genomes
+
+SYNOPSIS
+    tool-sub [FLAGS] [OPTIONS]
+
+FLAGS
+    -q, --quiet
+        Print less
+
+OPTIONS
+    -o, --output PATH
+        Write output here. [default:
+        out.tsv]
+
+FORMATS
+    Formats are tsv or:
+
+    • dense
+      • genome
+      • count
+    • sparse
+
+    Column  Meaning
+    ------  -----------
+    genome  Genome name
+    count   Read count
+
+    tool sub -o out.tsv
+      --quiet
+
+    Documentation is at the "docs"
+    <https://example.com/docs?a=1&b=2>
+
+    <https://example.com>
+
+NOTES
+    Genomes <named> A & B are compared
+    with tool sub --output out.tsv which
+    keeps going for a while longer than
+    the width.
+
+    /a/very/long/path/to/a/genome/file/which/does/not/fit/anywhere.fna
+
+EXIT STATUS
+    0
+        Successful program execution.
+
+    1
+        Unsuccessful program execution.
+
+    101
+        The program panicked.
+"#;
+        let expected_72 = r#"TOOL-SUB(1)
+
+NAME
+    tool-sub - Do things to genomes
+
+SYNOPSIS
+    tool-sub [FLAGS] [OPTIONS]
+
+FLAGS
+    -q, --quiet
+        Print less
+
+OPTIONS
+    -o, --output PATH
+        Write output here. [default: out.tsv]
+
+FORMATS
+    Formats are tsv or:
+
+    • dense
+      • genome
+      • count
+    • sparse
+
+    Column  Meaning
+    ------  -----------
+    genome  Genome name
+    count   Read count
+
+    tool sub -o out.tsv
+      --quiet
+
+    Documentation is at the "docs" <https://example.com/docs?a=1&b=2>
+
+    <https://example.com>
+
+NOTES
+    Genomes <named> A & B are compared with tool sub --output out.tsv
+    which keeps going for a while longer than the width.
+
+    /a/very/long/path/to/a/genome/file/which/does/not/fit/anywhere.fna
+
+EXIT STATUS
+    0
+        Successful program execution.
+
+    1
+        Unsuccessful program execution.
+
+    101
+        The program panicked.
+"#;
+        assert_eq!(expected_40, render_manual_plaintext(snapshot_manual(), 40));
+        assert_eq!(expected_72, render_manual_plaintext(snapshot_manual(), 72));
+
+        // Monospace text is moved to the next line as a whole rather than
+        // being broken at its spaces, even if it is wider than the width.
+        let roff = format!(
+            ".TH T 1\n.SH S\nRun {} now, or {} later\n",
+            monospace_roff("tool sub --output out.tsv"),
+            monospace_roff("tool sub --output out.tsv --threads 8 --quiet")
+        );
+        assert_eq!(
+            "T(1)\n\nS\n    Run\n    tool sub --output out.tsv\n    now, or\n    \
+             tool sub --output out.tsv --threads 8 --quiet\n    later\n",
+            plain_text_from_roff(&roff, 30)
+        );
+    }
+
+    /// The roff of section, as rendered in a Manual, from its heading on.
+    fn section_roff(section: Section) -> String {
+        let roff = Manual::new("t").custom(section).render();
+        let start = roff.find("\\fBt\\fR\n").unwrap() + "\\fBt\\fR\n".len();
+        let end = roff.find("\n.SH EXIT STATUS\n").unwrap();
+        roff[start..end].trim_end().to_string()
+    }
+
+    #[test]
+    fn test_environment_section() {
+        assert_eq!(
+            ".SH ENVIRONMENT\n\n\
+             .TP\n\\f[C]TMPDIR\\f[R]\nDirectory for temporary files.\n\
+             .TP\n\\f[C]TOOL_LOG\\f[R]\n\
+             Log filters e.g. 'tool=debug', as for\n\\&.RUST_LOG \\e.\n.PP",
+            section_roff(environment_section(&[
+                ("TMPDIR", "Directory for temporary files."),
+                ("TOOL_LOG", "Log filters e.g. 'tool=debug', as for\n.RUST_LOG \\."),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_examples_section() {
+        let command = "tool -f a.fna --name \"$HOME/x\" 'it''s' $((1+1))";
+        let section = examples()
+            .example("Map reads to \"3\" genomes", command)
+            .example("Then 'tidy' up", "rm -r \"${TMPDIR:-/tmp}\"/tool.*")
+            .section();
+        assert_eq!(
+            ".SH EXAMPLES\nMap reads to \"3\" genomes\n\
+             .RS 4\n.nf\n.ft C\n\
+             tool \\-f a.fna \\-\\-name \"$HOME/x\" 'it''s' $((1+1))\n\
+             .ft R\n.fi\n.RE\n\
+             \n\nThen 'tidy' up\n\
+             .RS 4\n.nf\n.ft C\nrm \\-r \"${TMPDIR:\\-/tmp}\"/tool.*\n.ft R\n.fi\n.RE",
+            section_roff(section)
+        );
+    }
+
+    #[test]
+    fn test_citation_and_see_also_sections() {
+        let section = citation_section(&[
+            "Smith et al. Genomes. Nature 2021. doi:10.1038/s41587-021-01011-1.",
+            "Jones (2020) https://doi.org/10.1000/xyz123, preprint",
+        ]);
+        assert_eq!(
+            ".SH CITATION\nSmith et al. Genomes. Nature 2021.\n\
+             .UR https://doi.org/10.1038/s41587\\-021\\-01011\\-1\n\
+             doi:10.1038/s41587\\-021\\-01011\\-1\n.UE \\&.\n\
+             \n\nJones (2020)\n\
+             .UR https://doi.org/10.1000/xyz123\nhttps://doi.org/10.1000/xyz123\n.UE ,\npreprint",
+            section_roff(section)
+        );
+        assert_eq!(
+            ".SH SEE ALSO\n\
+             \\f[B]samtools\\f[R](1), \\f[B]coverm\\-genome\\f[R](1), \\f[B]gzip\\f[R](1)",
+            section_roff(see_also_section(&[("samtools", 1), ("coverm-genome", 1), ("gzip", 1)]))
+        );
+    }
+
+    #[test]
+    fn test_write_all_manuals() {
+        let dir = tempfile::tempdir().unwrap();
+        let man_dir = dir.path().join("man1");
+        let manuals = || {
+            vec![
+                ("tool".to_string(), Manual::new("tool").about("Top level")),
+                ("tool-sub".to_string(), Manual::new("tool-sub").about("Subcommand")),
+            ]
+        };
+
+        let paths = write_all_manuals(manuals(), &man_dir, true).unwrap();
+        assert_eq!(vec![man_dir.join("tool.1.gz"), man_dir.join("tool-sub.1.gz")], paths);
+        for path in &paths {
+            let bytes = fs::read(path).unwrap();
+            assert_eq!([0x1f, 0x8b], bytes[..2], "{} is not gzipped", path.display());
+        }
+
+        let paths = write_all_manuals(manuals(), &man_dir, false).unwrap();
+        assert_eq!(vec![man_dir.join("tool.1"), man_dir.join("tool-sub.1")], paths);
+        assert!(fs::read_to_string(&paths[1]).unwrap().starts_with(".TH TOOL-SUB 1\n"));
+    }
+
+    #[test]
+    fn test_list_roff_nested() {
+        let text = |s: &str| ListItem::Text(s.to_string());
+        let items = [
+            text("Formats"),
+            ListItem::SubList(vec![
+                text("dense"),
+                ListItem::SubList(vec![text("genome"), text(".count")]),
+                text("sparse"),
+            ]),
+            text("Done"),
+        ];
+        assert_eq!(
+            "\n.IP \\[bu] 2\nFormats\n\
+             .RS 2\n.IP \\[bu] 2\ndense\n\
+             .RS 2\n.IP \\[bu] 2\ngenome\n.IP \\[bu] 2\n\\&.count\n.RE\n\
+             .IP \\[bu] 2\nsparse\n.RE\n\
+             .IP \\[bu] 2\nDone\n.PP\n",
+            list_roff_nested(&items)
+        );
+        assert_eq!(list_roff_nested(&[text("a"), text("b")]), list_roff(&["a", "b"]));
+    }
+
+    #[test]
+    fn test_table_roff_wraps_long_cells() {
+        let description = vec!["word"; 80].join(" ");
+        let rows: &[&[&str]] = &[&["Column", "Description"], &["genome", &description]];
+        // The last column is wrapped to what is left of TABLE_WIDTH.
+        assert_eq!(
+            format!(
+                "\n.TS\ntab(@);\nl lw(61n) .\n\
+                 T{{\nColumn\nT}}@T{{\nDescription\nT}}\n_\n\
+                 T{{\ngenome\nT}}@T{{\n{}\nT}}\n.TE\n",
+                description
+            ),
+            table_roff(rows)
+        );
+        let options = TableOptions {
+            widths: vec![None, Some(40)],
+            ..Default::default()
+        };
+        assert!(table_roff_options(rows, &options).contains("\nl lw(40n) .\n"));
+        let options = TableOptions {
+            wrap_columns: Some(vec![]),
+            ..Default::default()
+        };
+        assert!(table_roff_options(rows, &options).contains("\nl l .\n"));
+        // Tables which fit are not wrapped
+        assert!(table_roff(&[&["genome", "Genome name"]]).contains("\nl l .\n"));
+    }
+
+    #[test]
+    fn test_default_roff_variants() {
+        assert_eq!("[default: \\f[C]fna\\f[R]]", default_roff("fna"));
+        assert_eq!("[default: \\f[C]\\&.\\e\\f[R]]", default_roff(".\\"));
+        assert_eq!("[default: not set]", default_none_roff());
+        assert_eq!(
+            "[default: \\f[C]fna\\f[R], \\f[C]fa\\f[R], \\f[C]a\\eb\\f[R]]",
+            default_list_roff(&["fna", "fa", "a\\b"])
+        );
+        assert_eq!("[default: not set]", default_list_roff(&[]));
+        assert_eq!("[default: \\f[C]10\\f[R]]", default_option_roff(Some("10")));
+        assert_eq!("[default: not set]", default_option_roff(None));
+    }
+
+    #[test]
+    fn test_monospace_roff_multiline_leading_spaces() {
+        assert_eq!(
+            "\n.nf\n.ft C\n  first\n    second \\e\n      .third\n.ft R\n.fi\n",
+            monospace_roff_multiline("  first\n    second \\\n      .third")
+        );
+    }
+
+    #[test]
+    fn test_print_full_help_if_needed_flags() {
+        let command = clap::Command::new("tool").args([
+            clap::Arg::new("full-help").long("full-help").action(clap::ArgAction::SetTrue),
+            clap::Arg::new("full-help-roff")
+                .long("full-help-roff")
+                .action(clap::ArgAction::SetTrue),
+        ]);
+        let manual = || Manual::new("tool").about("Do things").flag(Flag::new().long("--dry-run"));
+        let roff = full_help_roff(manual());
+        let text = plain_text_from_roff(&roff, full_help_width());
+        assert!(text.contains("--dry-run"), "{}", text);
+        // Neither 'man' nor a pager can be found, so the text is printed
+        let dir = tempfile::tempdir().unwrap();
+        let full_help_env =
+            FullHelpEnv { path: Some(dir.path().as_os_str().to_owned()), pagers: vec![] };
+        let print = |args: &[&str], is_terminal: bool| {
+            let m = command.clone().try_get_matches_from(args).unwrap();
+            let mut out = Vec::new();
+            let handled =
+                print_full_help_if_needed_to(&m, manual, &mut out, is_terminal, &full_help_env);
+            (handled, String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!((Ok(false), String::new()), print(&["tool"], false));
+        assert_eq!((Ok(true), format!("{}\n", roff)), print(&["tool", "--full-help-roff"], false));
+        assert_eq!((Ok(true), text.clone()), print(&["tool", "--full-help"], false));
+        assert_eq!((Ok(true), text.clone()), print(&["tool", "--full-help"], true));
+        // --full-help takes precedence
+        assert_eq!(
+            (Ok(true), text),
+            print(&["tool", "--full-help", "--full-help-roff"], false)
+        );
+        // The manual is only made when needed
+        let m = command.try_get_matches_from(["tool"]).unwrap();
+        let handled = print_full_help_if_needed_to(
+            &m,
+            || -> Manual { panic!("manual made") },
+            &mut Vec::new(),
+            false,
+            &full_help_env,
+        );
+        assert_eq!(Ok(false), handled);
+    }
+}
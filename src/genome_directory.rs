@@ -0,0 +1,678 @@
+use std;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::genome_manifest::*;
+use crate::genome_specification::*;
+
+/// Find the genome FASTA files in dir, i.e. those whose file names end with
+/// one of extensions, e.g. ["fna", "fa"]. If recursive, subdirectories are
+/// searched too. See parse_genome_fasta_directory_with_options().
+pub fn parse_genome_fasta_directory(
+    dir: &Path,
+    extensions: &[&str],
+    recursive: bool,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    parse_genome_fasta_directory_with_options(
+        dir,
+        extensions,
+        recursive,
+        &GenomeSpecificationOptions::default(),
+    )
+}
+
+/// Find the genome FASTA files in dir, i.e. those whose file names end with
+/// one of extensions, e.g. ["fna", "fa"]. This is how --genome-fasta-directory
+/// is searched. A leading dot on an extension is ignored, and an extension of
+/// "auto" means whichever of AUTO_DETECTED_EXTENSIONS are present. Hidden
+/// files and subdirectories, whose names start with '.', are skipped unless
+/// options.include_hidden_files. Paths are returned sorted. Options which do
+/// not concern finding files, such as validation, are ignored.
+pub fn parse_genome_fasta_directory_with_options(
+    dir: &Path,
+    extensions: &[&str],
+    recursive: bool,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    let extensions: Vec<String> = extensions.iter().map(|e| e.to_string()).collect();
+    let extensions = resolve_genome_fasta_extensions(&[dir], &extensions, recursive, options)?;
+    let genome_fasta_files = find_genome_fasta_files_in_directory(
+        dir,
+        &extensions,
+        recursive,
+        options,
+        options.max_genomes.map(|max| (max, 0)),
+    )?;
+    if genome_fasta_files.is_empty() && options.required_genomes() > 0 {
+        return Err(GenomeSpecificationError::EmptyDirectory {
+            dirs: vec![dir.to_path_buf()],
+            extensions_present: extensions_present(&[dir], recursive, options),
+        });
+    }
+    Ok(genome_fasta_files)
+}
+
+/// Normalise genome FASTA extensions, removing any leading dot and duplicates,
+/// and lowercasing them unless extensions are matched case-sensitively. If the
+/// only extension is 'auto', all of AUTO_DETECTED_EXTENSIONS are returned.
+pub(crate) fn normalise_genome_fasta_extensions(
+    extensions: &[String],
+    options: &GenomeSpecificationOptions,
+) -> Vec<String> {
+    let extensions: Vec<String> = match is_auto_genome_fasta_extension(extensions) {
+        true => AUTO_DETECTED_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+        false => {
+            let mut normalised: Vec<String> = vec![];
+            for e in extensions {
+                let e = e.trim();
+                let e = e.strip_prefix('.').unwrap_or(e);
+                if !e.is_empty() && !normalised.iter().any(|e2| e2 == e) {
+                    normalised.push(e.to_string());
+                }
+            }
+            normalised
+        }
+    };
+    match options.case_sensitive_extensions {
+        true => extensions,
+        false => extensions.iter().map(|e| e.to_ascii_lowercase()).collect(),
+    }
+}
+
+/// Normalise genome FASTA extensions as normalise_genome_fasta_extensions()
+/// does, and if the extension is 'auto', work out which extensions are present
+/// in dirs.
+pub(crate) fn resolve_genome_fasta_extensions(
+    dirs: &[&Path],
+    extensions: &[String],
+    recursive: bool,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<String>, GenomeSpecificationError> {
+    let normalised = normalise_genome_fasta_extensions(extensions, options);
+    match is_auto_genome_fasta_extension(extensions) {
+        true => detect_genome_fasta_extensions(dirs, recursive, &normalised, options),
+        false => Ok(normalised),
+    }
+}
+
+fn is_auto_genome_fasta_extension(extensions: &[String]) -> bool {
+    extensions.len() == 1 && extensions[0].eq_ignore_ascii_case(AUTO_GENOME_FASTA_EXTENSION)
+}
+
+/// Find the genome FASTA files in dir with one of extensions (as returned by
+/// resolve_genome_fasta_extensions()), sorted. If options.directory_manifests
+/// is set, the files recorded in a manifest are used if it is up to date, and
+/// otherwise the manifest is written. max_genomes is the maximum number of
+/// genomes along with the number already found in other directories.
+pub(crate) fn find_genome_fasta_files_in_directory(
+    dir: &Path,
+    extensions: &[String],
+    recursive: bool,
+    options: &GenomeSpecificationOptions,
+    max_genomes: Option<(usize, usize)>,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    let manifest_settings = format!(
+        "extensions={};recursive={};case_sensitive={};include_hidden={}",
+        extensions.join(","),
+        recursive,
+        options.case_sensitive_extensions,
+        options.include_hidden_files
+    );
+    let manifest_path = options
+        .directory_manifests
+        .as_ref()
+        .map(|location| location.manifest_path(dir));
+    let manifest_paths = manifest_path
+        .as_ref()
+        .and_then(|m| read_current_manifest(m, dir, &manifest_settings));
+    let mut paths = match manifest_paths {
+        Some(paths) => paths,
+        None => {
+            let mut paths = vec![];
+            let is_genome_fasta = |file: &Path| {
+                !skip_hidden_file(file, options)
+                    && has_genome_fasta_extension(file, extensions, options, "directory entry")
+            };
+            let mut walk = DirectoryWalk::new(recursive, &is_genome_fasta);
+            walk.skip_hidden_directories = !options.include_hidden_files;
+            walk.max_entries = max_genomes;
+            walk.unreadable_entries = options.unreadable_entries;
+            walk.progress_interval = match options.progress_interval {
+                Some(interval) => interval,
+                None => DEFAULT_PROGRESS_INTERVAL,
+            };
+            collect_directory_entries(dir, &mut walk, &mut paths)?;
+            if let Some(manifest_path) = &manifest_path {
+                // Only recursive searches record the directories they visit,
+                // which are canonical, so record dir in the same way.
+                let mut visited: Vec<PathBuf> = match recursive {
+                    true => walk.visited.into_iter().collect(),
+                    false => vec![std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())],
+                };
+                visited.sort();
+                write_manifest(manifest_path, dir, &manifest_settings, &visited, &paths);
+            }
+            paths
+        }
+    };
+    // Sort so that results do not depend on the order the filesystem happens
+    // to list entries in.
+    paths.sort();
+    let mut genome_fasta_files: Vec<PathBuf> = vec![];
+    let mut broken_symlinks = vec![];
+    for file in paths {
+        // The name of a symlink rather than its target determines whether it
+        // matches, so check the target actually exists.
+        if !file.exists() && file.is_symlink() {
+            broken_symlinks.push(file);
+        } else {
+            genome_fasta_files.push(file);
+        }
+    }
+    if !broken_symlinks.is_empty() {
+        match options.broken_symlinks {
+            BrokenSymlinkAction::Skip => warn!(
+                "Not using {} broken symlink(s) in genome-fasta-directory: {}",
+                broken_symlinks.len(),
+                broken_symlinks
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            BrokenSymlinkAction::Fail => {
+                return Err(GenomeSpecificationError::BrokenSymlinks {
+                    links: broken_symlinks,
+                })
+            }
+        }
+    }
+    Ok(genome_fasta_files)
+}
+
+/// Deal with the directory dir having been given to --genome-fasta-files,
+/// according to options.directories_in_files. Its genome FASTA files are found
+/// as if it had been given to --genome-fasta-directory, using the given
+/// --genome-fasta-extension values.
+pub(crate) fn genome_fasta_files_argument_directory(
+    dir: &Path,
+    extensions: &[String],
+    recursive: bool,
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<PathBuf>, GenomeSpecificationError> {
+    match options.directories_in_files {
+        DirectoryInFilesAction::Expand => {
+            info!(
+                "Using genome FASTA files in directory '{}' given to --genome-fasta-files",
+                dir.display()
+            );
+            let extensions =
+                resolve_genome_fasta_extensions(&[dir], extensions, recursive, options)?;
+            find_genome_fasta_files_in_directory(dir, &extensions, recursive, options, None)
+        }
+        DirectoryInFilesAction::Fail => {
+            Err(GenomeSpecificationError::DirectoryInGenomeFastaFiles {
+                path: dir.to_path_buf(),
+            })
+        }
+    }
+}
+
+/// Work out which of candidates are the extensions of files in dirs, for when
+/// the genome FASTA extension is 'auto'. This requires an extra pass over the
+/// directories.
+fn detect_genome_fasta_extensions(
+    dirs: &[&Path],
+    recursive: bool,
+    candidates: &[String],
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<Vec<String>, GenomeSpecificationError> {
+    let matching_candidate = |file: &Path| {
+        let mut file_name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !options.case_sensitive_extensions {
+            file_name = file_name.to_ascii_lowercase();
+        }
+        candidates
+            .iter()
+            .position(|e| file_name_has_extension(&file_name, e))
+    };
+    let mut files = vec![];
+    for dir in dirs {
+        let is_candidate =
+            |file: &Path| !skip_hidden_file(file, options) && matching_candidate(file).is_some();
+        let mut walk = DirectoryWalk::new(recursive, &is_candidate);
+        walk.skip_hidden_directories = !options.include_hidden_files;
+        walk.unreadable_entries = options.unreadable_entries;
+        collect_directory_entries(dir, &mut walk, &mut files)?;
+    }
+    let mut counts = vec![0; candidates.len()];
+    for file in &files {
+        if let Some(i) = matching_candidate(file) {
+            counts[i] += 1;
+        }
+    }
+    let counts: Vec<(String, usize)> = candidates
+        .iter()
+        .cloned()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    for (extension, count) in &counts {
+        info!(
+            "Detected {} genome FASTA file(s) with extension '{}'",
+            count, extension
+        );
+    }
+    if counts.len() > 1 && options.multiple_auto_extensions == MultipleExtensionAction::Fail {
+        return Err(GenomeSpecificationError::AmbiguousExtension { counts });
+    }
+    Ok(counts.into_iter().map(|(extension, _)| extension).collect())
+}
+
+/// Whether file should be skipped when searching a directory because its name
+/// starts with '.', e.g. '.DS_Store' or '._genome.fna' files made by macOS.
+pub(crate) fn skip_hidden_file(file: &Path, options: &GenomeSpecificationOptions) -> bool {
+    if is_hidden(file) && !options.include_hidden_files {
+        debug!(
+            "Not using hidden directory entry '{}' as a genome FASTA file",
+            file.display()
+        );
+        return true;
+    }
+    false
+}
+
+/// Whether the name of file starts with '.'.
+fn is_hidden(file: &Path) -> bool {
+    file.file_name()
+        .map(|n| n.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// The distinct extensions of the files in dirs, for explaining why no genomes
+/// were found.
+pub(crate) fn extensions_present(
+    dirs: &[&Path],
+    recursive: bool,
+    options: &GenomeSpecificationOptions,
+) -> Vec<String> {
+    let mut files = vec![];
+    for dir in dirs {
+        // Any error would have been reported when first reading the directory
+        let is_not_hidden = |file: &Path| !skip_hidden_file(file, options);
+        let mut walk = DirectoryWalk::new(recursive, &is_not_hidden);
+        walk.skip_hidden_directories = !options.include_hidden_files;
+        if collect_directory_entries(dir, &mut walk, &mut files).is_err() {
+            return vec![];
+        }
+    }
+    let extensions: BTreeSet<String> = files
+        .iter()
+        .filter_map(|f| f.file_name())
+        .filter_map(|n| extension_of_file_name(&n.to_string_lossy()))
+        .collect();
+    extensions.into_iter().collect()
+}
+
+/// Whether the file name of file ends with one of extensions (as returned by
+/// normalise_genome_fasta_extensions()). If not, the reason it is not being
+/// used is logged, describing file as e.g. a "directory entry".
+pub(crate) fn has_genome_fasta_extension(
+    file: &Path,
+    extensions: &[String],
+    options: &GenomeSpecificationOptions,
+    description: &str,
+) -> bool {
+    let case_sensitive = options.case_sensitive_extensions;
+    let mut file_name = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !case_sensitive {
+        file_name = file_name.to_ascii_lowercase();
+    }
+    if extensions
+        .iter()
+        .any(|e| file_name_has_extension(&file_name, e))
+    {
+        return true;
+    }
+    if let Some((e, compression)) = extensions.iter().find_map(|e| {
+        COMPRESSION_SUFFIXES
+            .iter()
+            .find(|c| file_name_has_extension(&file_name, &format!("{}.{}", e, c)))
+            .map(|c| (e, c))
+    }) {
+        crate::info_limited!(
+            SKIPPED_GENOME_MESSAGE_KEY,
+            SKIPPED_GENOME_MESSAGE_LIMIT,
+            "Not using {} '{}' as a genome FASTA file, as it appears to be \
+             compressed. Specify the extension as '{}.{}' to use compressed genomes",
+            description,
+            file.display(),
+            e,
+            compression
+        );
+    } else if case_sensitive
+        && extensions.iter().any(|e| {
+            file_name_has_extension(&file_name.to_ascii_lowercase(), &e.to_ascii_lowercase())
+        })
+    {
+        crate::info_limited!(
+            SKIPPED_GENOME_MESSAGE_KEY,
+            SKIPPED_GENOME_MESSAGE_LIMIT,
+            "Not using {} '{}' as a genome FASTA file, as its extension differs \
+             in case from the extension(s) {}. Case-insensitive extension \
+             matching can be enabled to use it",
+            description,
+            file.display(),
+            format_extensions(extensions)
+        );
+    } else {
+        crate::info_limited!(
+            SKIPPED_GENOME_MESSAGE_KEY,
+            SKIPPED_GENOME_MESSAGE_LIMIT,
+            "Not using {} '{}' as a genome FASTA file, as it does not end with \
+             the extension(s) {}",
+            description,
+            file.display(),
+            format_extensions(extensions)
+        );
+    }
+    false
+}
+
+fn format_extensions(extensions: &[String]) -> String {
+    extensions
+        .iter()
+        .map(|e| format!("'.{}'", e))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Deal with an entry of the genome FASTA directory dir which could not be
+/// read, either logging a warning or returning an Err, according to action.
+pub(crate) fn unreadable_directory_entry(
+    dir: &Path,
+    entry: Option<PathBuf>,
+    source: std::io::Error,
+    action: UnreadableEntryAction,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let e = GenomeSpecificationError::UnreadableDirectoryEntry {
+        dir: dir.to_path_buf(),
+        entry,
+        source,
+    };
+    match action {
+        UnreadableEntryAction::Skip => {
+            warn!("{}. Skipping it", e);
+            Ok(())
+        }
+        UnreadableEntryAction::Fail => Err(e),
+    }
+}
+
+/// Number of directory entries between progress messages while searching a
+/// genome FASTA directory, unless GenomeSpecificationOptions::progress_interval
+/// is set.
+pub const DEFAULT_PROGRESS_INTERVAL: usize = 10_000;
+
+/// Settings and state of a search through a directory by
+/// collect_directory_entries().
+struct DirectoryWalk<'a> {
+    /// Descend into subdirectories (including symlinked ones) rather than
+    /// returning them.
+    recursive: bool,
+    /// Canonical paths of the directories visited so far, so that each is only
+    /// visited once and symlink loops terminate. Only used when recursive.
+    visited: HashSet<PathBuf>,
+    /// Which directory entries to return.
+    is_genome_fasta: &'a dyn Fn(&Path) -> bool,
+    /// Do not descend into subdirectories whose names start with '.', e.g.
+    /// '.snakemake' or '.git'.
+    skip_hidden_directories: bool,
+    /// The maximum number of entries to return, along with the number already
+    /// found in other directories.
+    max_entries: Option<(usize, usize)>,
+    /// Log progress after every this many entries, or never if 0.
+    progress_interval: usize,
+    /// Number of entries seen so far.
+    scanned: usize,
+    /// What to do with entries which cannot be read.
+    unreadable_entries: UnreadableEntryAction,
+}
+
+impl<'a> DirectoryWalk<'a> {
+    fn new(recursive: bool, is_genome_fasta: &'a dyn Fn(&Path) -> bool) -> DirectoryWalk<'a> {
+        DirectoryWalk {
+            recursive,
+            visited: HashSet::new(),
+            is_genome_fasta,
+            skip_hidden_directories: false,
+            max_entries: None,
+            progress_interval: 0,
+            scanned: 0,
+            unreadable_entries: UnreadableEntryAction::Skip,
+        }
+    }
+}
+
+/// Push the entries of dir for which walk.is_genome_fasta returns true onto
+/// entries, descending into subdirectories if walk.recursive.
+fn collect_directory_entries(
+    dir: &Path,
+    walk: &mut DirectoryWalk,
+    entries: &mut Vec<PathBuf>,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let unreadable = |e| GenomeSpecificationError::UnreadableDirectory {
+        dir: dir.to_path_buf(),
+        source: e,
+    };
+    if walk.recursive {
+        let canonical = std::fs::canonicalize(dir).map_err(unreadable)?;
+        if !walk.visited.insert(canonical) {
+            debug!("Not descending into already visited directory '{}'", dir.display());
+            return Ok(());
+        }
+    }
+    let paths = std::fs::read_dir(dir).map_err(unreadable)?;
+    for path in paths {
+        let file = match path {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                unreadable_directory_entry(dir, None, e, walk.unreadable_entries)?;
+                continue;
+            }
+        };
+        walk.scanned += 1;
+        if walk.recursive && file.is_dir() && walk.skip_hidden_directories && is_hidden(&file) {
+            debug!("Not searching hidden directory '{}' for genomes", file.display());
+        } else if walk.recursive && file.is_dir() {
+            // Subdirectories which cannot be read are entries of dir which
+            // cannot be read, whereas failures further down have already been
+            // dealt with.
+            match collect_directory_entries(&file, walk, entries) {
+                Err(GenomeSpecificationError::UnreadableDirectory { source, .. }) => {
+                    unreadable_directory_entry(dir, Some(file), source, walk.unreadable_entries)?
+                }
+                result => result?,
+            }
+        } else if (walk.is_genome_fasta)(&file) {
+            entries.push(file);
+            // Bail out as soon as there are too many, rather than after
+            // walking what may be an enormous directory.
+            if let Some((max, found_before)) = walk.max_entries {
+                if found_before + entries.len() > max {
+                    return Err(GenomeSpecificationError::TooManyGenomes {
+                        max_genomes: max,
+                        found: found_before + entries.len(),
+                    });
+                }
+            }
+        }
+        if walk.progress_interval > 0 && walk.scanned.is_multiple_of(walk.progress_interval) {
+            info!(
+                "Scanned {} directory entries, found {} genome FASTA files so far",
+                format_count(walk.scanned),
+                format_count(entries.len())
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome_manifest::GenomeDirectoryManifest;
+
+    /// Create an empty file for each of names in dir.
+    fn touch(dir: &Path, names: &[&str]) {
+        for name in names {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_genome_fasta_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["b.fna", "a.fna", "c.fa", "notes.txt"]);
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        touch(&dir.path().join("sub"), &["d.fna"]);
+
+        let flat = vec![dir.path().join("a.fna"), dir.path().join("b.fna")];
+        assert_eq!(flat, parse_genome_fasta_directory(dir.path(), &["fna"], false).unwrap());
+        assert_eq!(flat, parse_genome_fasta_directory(dir.path(), &[".fna"], false).unwrap());
+        assert_eq!(
+            vec![
+                dir.path().join("a.fna"),
+                dir.path().join("b.fna"),
+                dir.path().join("c.fa"),
+                dir.path().join("sub/d.fna"),
+            ],
+            parse_genome_fasta_directory(dir.path(), &[".fna", "fa"], true).unwrap()
+        );
+        assert_eq!(
+            Vec::<PathBuf>::new(),
+            parse_genome_fasta_directory(dir.path(), &["fasta"], false).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_genome_fasta_directory_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        match parse_genome_fasta_directory(&missing, &["fna"], false) {
+            Err(GenomeSpecificationError::UnreadableDirectory { dir, .. }) => {
+                assert_eq!(missing, dir)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_extension_matching_case() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["a.fna", "b.FNA", "c.txt"]);
+
+        let found = parse_genome_fasta_directory(dir.path(), &["fna"], false).unwrap();
+        assert_eq!(vec![dir.path().join("a.fna"), dir.path().join("b.FNA")], found);
+
+        let case_sensitive = GenomeSpecificationOptions {
+            case_sensitive_extensions: true,
+            ..Default::default()
+        };
+        let found =
+            parse_genome_fasta_directory_with_options(dir.path(), &["fna"], false, &case_sensitive)
+                .unwrap();
+        assert_eq!(vec![dir.path().join("a.fna")], found);
+    }
+
+    #[test]
+    fn test_hidden_files_and_directories_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["a.fna", ".DS_Store", "._foo.fna"]);
+        std::fs::create_dir(dir.path().join(".snakemake")).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        touch(&dir.path().join(".snakemake"), &["tmp.fna"]);
+        touch(&dir.path().join("sub"), &["b.fna"]);
+
+        let found = parse_genome_fasta_directory(dir.path(), &["fna"], true).unwrap();
+        assert_eq!(vec![dir.path().join("a.fna"), dir.path().join("sub/b.fna")], found);
+
+        let include_hidden = GenomeSpecificationOptions {
+            include_hidden_files: true,
+            ..Default::default()
+        };
+        let found =
+            parse_genome_fasta_directory_with_options(dir.path(), &["fna"], true, &include_hidden)
+                .unwrap();
+        assert_eq!(
+            vec![
+                dir.path().join("._foo.fna"),
+                dir.path().join(".snakemake/tmp.fna"),
+                dir.path().join("a.fna"),
+                dir.path().join("sub/b.fna"),
+            ],
+            found
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_directory_manifest_round_trip_with_relative_directory() {
+        let genomes = tempfile::tempdir().unwrap();
+        let manifests = tempfile::tempdir().unwrap();
+        for name in ["b.fna", "a.fna", "c.fa"] {
+            std::fs::write(genomes.path().join(name), ">contig\nACGT\n").unwrap();
+        }
+        // The genome directory relative to the current directory
+        let relative: PathBuf = std::env::current_dir()
+            .unwrap()
+            .components()
+            .skip(1)
+            .map(|_| "..")
+            .collect::<PathBuf>()
+            .join(genomes.path().strip_prefix("/").unwrap());
+        let options = GenomeSpecificationOptions {
+            directory_manifests: Some(ManifestLocation::InDirectory(
+                manifests.path().to_path_buf(),
+            )),
+            ..Default::default()
+        };
+        let extensions = vec!["fna".to_string()];
+        let found =
+            find_genome_fasta_files_in_directory(&relative, &extensions, false, &options, None)
+                .unwrap();
+        assert_eq!(vec![relative.join("a.fna"), relative.join("b.fna")], found);
+
+        let manifest_path = options
+            .directory_manifests
+            .as_ref()
+            .unwrap()
+            .manifest_path(&relative);
+        let manifest = GenomeDirectoryManifest::read(std::io::BufReader::new(
+            std::fs::File::open(&manifest_path).unwrap(),
+        ))
+        .unwrap();
+        let canonical = std::fs::canonicalize(genomes.path()).unwrap();
+        assert_eq!(
+            vec![canonical],
+            manifest.directories.iter().map(|(d, _)| d.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![PathBuf::from("a.fna"), PathBuf::from("b.fna")],
+            manifest.genomes.iter().map(|g| g.path.clone()).collect::<Vec<_>>()
+        );
+        assert!(manifest.is_current());
+
+        // Found again from the manifest
+        let found_again =
+            find_genome_fasta_files_in_directory(&relative, &extensions, false, &options, None)
+                .unwrap();
+        assert_eq!(found, found_again);
+    }
+}
@@ -0,0 +1,129 @@
+use std;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::genome_specification::*;
+
+/// Prefixes of genome FASTA paths which are downloaded rather than being read
+/// from the filesystem.
+pub const REMOTE_GENOME_PREFIXES: &[&str] = &["http://", "https://", "ftp://"];
+
+/// Whether a genome FASTA path is a URL to be downloaded, i.e. starts with one
+/// of REMOTE_GENOME_PREFIXES.
+pub fn is_remote_genome_path<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref().to_string_lossy();
+    REMOTE_GENOME_PREFIXES.iter().any(|p| path.starts_with(p))
+}
+
+/// Download each genome FASTA file whose path is a URL into a new temporary
+/// directory, replacing its path with that of the downloaded file. Downloaded
+/// files keep the file name from the end of the URL, so that genome names are
+/// unchanged. If several URLs end in the same file name, the later ones are
+/// put in numbered subdirectories. The temporary directory is returned if
+/// anything was downloaded, and the downloaded files are deleted when it is
+/// dropped.
+///
+/// Downloading requires the remote-genomes feature. Without it, an Err is
+/// returned if any path is a URL.
+pub fn download_remote_genome_fasta_files(
+    files: Vec<GenomeFastaFile>,
+) -> std::result::Result<(Option<tempfile::TempDir>, Vec<GenomeFastaFile>), GenomeSpecificationError>
+{
+    let first_url = match files.iter().find(|f| is_remote_genome_path(&f.path)) {
+        Some(f) => f.path.to_string_lossy().to_string(),
+        None => return Ok((None, files)),
+    };
+    let temporary_directory = tempfile::Builder::new()
+        .prefix("bird_tool_utils_genome_download")
+        .tempdir()
+        .map_err(|e| GenomeSpecificationError::DownloadFailed {
+            url: first_url,
+            message: format!("could not create temporary directory: {}", e),
+        })?;
+    let mut used_file_names = HashSet::new();
+    let mut num_collisions = 0;
+    let mut local_files = Vec::with_capacity(files.len());
+    for file in files {
+        if !is_remote_genome_path(&file.path) {
+            local_files.push(file);
+            continue;
+        }
+        let url = file.path.to_string_lossy().to_string();
+        let file_name = file_name_from_url(&url);
+        let destination = match used_file_names.insert(file_name.clone()) {
+            true => temporary_directory.path().join(&file_name),
+            false => {
+                num_collisions += 1;
+                let dir = temporary_directory.path().join(num_collisions.to_string());
+                std::fs::create_dir(&dir).map_err(|e| {
+                    GenomeSpecificationError::DownloadFailed {
+                        url: url.clone(),
+                        message: e.to_string(),
+                    }
+                })?;
+                dir.join(&file_name)
+            }
+        };
+        info!("Downloading genome FASTA file from '{}'", url);
+        download(&url, &destination)
+            .map_err(|message| GenomeSpecificationError::DownloadFailed { url, message })?;
+        local_files.push(GenomeFastaFile {
+            path: destination,
+            ..file
+        });
+    }
+    Ok((Some(temporary_directory), local_files))
+}
+
+/// The file name at the end of url, ignoring any query or fragment.
+fn file_name_from_url(url: &str) -> String {
+    let without_query = url.split(['?', '#']).next().unwrap_or(url);
+    match without_query.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => "genome".to_string(),
+    }
+}
+
+#[cfg(feature = "remote-genomes")]
+fn download(url: &str, destination: &Path) -> std::result::Result<(), String> {
+    let mut file = std::fs::File::create(destination).map_err(|e| e.to_string())?;
+    match url.starts_with("ftp://") {
+        true => {
+            let (host, path) = url["ftp://".len()..]
+                .split_once('/')
+                .ok_or_else(|| "URL does not include a file path".to_string())?;
+            let address = match host.contains(':') {
+                true => host.to_string(),
+                false => format!("{}:21", host),
+            };
+            let mut ftp = suppaftp::FtpStream::connect(address).map_err(|e| e.to_string())?;
+            ftp.login("anonymous", "anonymous")
+                .map_err(|e| e.to_string())?;
+            ftp.transfer_type(suppaftp::types::FileType::Binary)
+                .map_err(|e| e.to_string())?;
+            ftp.retr(&format!("/{}", path), |reader| {
+                std::io::copy(reader, &mut file).map_err(suppaftp::FtpError::ConnectionError)
+            })
+            .map_err(|e| e.to_string())?;
+            // The file has been retrieved, so failing to log out is harmless.
+            let _ = ftp.quit();
+        }
+        false => {
+            let response = ureq::get(url).call().map_err(|e| match e {
+                ureq::Error::StatusCode(status) => format!("HTTP status {}", status),
+                e => e.to_string(),
+            })?;
+            std::io::copy(&mut response.into_body().into_reader(), &mut file)
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "remote-genomes"))]
+fn download(_url: &str, _destination: &Path) -> std::result::Result<(), String> {
+    Err("downloading genomes is not supported as bird_tool_utils was built \
+        without the remote-genomes feature"
+        .to_string())
+}
+
@@ -0,0 +1,386 @@
+use std;
+use std::collections::HashSet;
+use std::fs::ReadDir;
+use std::io::{BufRead, Lines};
+use std::path::{Path, PathBuf};
+
+use crate::clap_utils::{
+    expand_genome_fasta_glob, flag_is_set, genome_fasta_extension_values,
+    genome_fasta_files_values, is_glob_pattern,
+};
+use crate::genome_directory::*;
+use crate::genome_download::is_remote_genome_path;
+use crate::genome_specification::*;
+
+/// Iterator over the genome FASTA files specified by clap arguments defined in
+/// the common way (see clap_utils::add_genome_specification_arguments()).
+/// Unlike clap_utils::parse_list_of_genome_fasta_paths(), paths are found as
+/// they are needed rather than all up front, which suits tools which process
+/// one genome at a time. Because of this, paths found in directories are not
+/// sorted, repeated paths are not removed, and genome FASTA lists are not
+/// checked for duplicate entries.
+///
+/// Errors are yielded as Err items. Iteration carries on after an error
+/// reading a directory (unreadable entries are only an error if
+/// GenomeSpecificationOptions::unreadable_entries is Fail), but stops after an
+/// error reading a genome FASTA list.
+/// Genomes extracted from --genome-fasta-archive are deleted when the iterator
+/// is dropped.
+pub struct GenomeFastaFileIterator {
+    source: GenomeFastaSource,
+    exclusions: ExclusionPatterns,
+    options: GenomeSpecificationOptions,
+    _temporary_directories: Vec<tempfile::TempDir>,
+}
+
+enum GenomeFastaSource {
+    Paths(std::vec::IntoIter<PathBuf>),
+    Files {
+        values: std::vec::IntoIter<String>,
+        expanded: std::vec::IntoIter<PathBuf>,
+        /// Used for directories given as files.
+        extensions: Vec<String>,
+        recursive: bool,
+    },
+    Directories {
+        dirs: std::vec::IntoIter<PathBuf>,
+        /// Directories currently being read, innermost last.
+        stack: Vec<(PathBuf, ReadDir)>,
+        visited: HashSet<PathBuf>,
+        recursive: bool,
+        extensions: Vec<String>,
+    },
+    List {
+        path: PathBuf,
+        lines: Lines<Box<dyn BufRead>>,
+        line_number: usize,
+        is_gzip: bool,
+        /// Directory relative paths are resolved against, if not the current
+        /// working directory.
+        base: Option<PathBuf>,
+    },
+    Finished,
+}
+
+impl GenomeFastaFileIterator {
+    /// Create an iterator over the genome FASTA files specified in m.
+    pub fn new(
+        m: &clap::ArgMatches,
+    ) -> std::result::Result<GenomeFastaFileIterator, GenomeSpecificationError> {
+        GenomeFastaFileIterator::with_options(m, &GenomeSpecificationOptions::default())
+    }
+
+    /// Create an iterator over the genome FASTA files specified in m. Only
+    /// options which concern finding files are used, e.g. validation is not.
+    pub fn with_options(
+        m: &clap::ArgMatches,
+        options: &GenomeSpecificationOptions,
+    ) -> std::result::Result<GenomeFastaFileIterator, GenomeSpecificationError> {
+        let id = |name: &str| options.argument_id(name);
+        let mut temporary_directories = vec![];
+        let source = if m.contains_id(&id("genome-fasta-files")) {
+            let values = genome_fasta_files_values(m, options)?;
+            GenomeFastaSource::Files {
+                values: values.into_iter(),
+                expanded: vec![].into_iter(),
+                extensions: genome_fasta_extension_values(m, options),
+                recursive: flag_is_set(m, &id("genome-fasta-directory-recursive")),
+            }
+        } else if m.contains_id(&id("genome-fasta-directory")) {
+            let dirs: Vec<PathBuf> = m
+                .get_many::<String>(&id("genome-fasta-directory"))
+                .unwrap()
+                .map(PathBuf::from)
+                .collect();
+            let recursive = flag_is_set(m, &id("genome-fasta-directory-recursive"));
+            let extensions = resolve_genome_fasta_extensions(
+                &dirs.iter().map(|d| d.as_path()).collect::<Vec<_>>(),
+                &genome_fasta_extension_values(m, options),
+                recursive,
+                options,
+            )?;
+            GenomeFastaSource::Directories {
+                dirs: dirs.into_iter(),
+                stack: vec![],
+                visited: HashSet::new(),
+                recursive,
+                extensions,
+            }
+        } else if m.contains_id(&id("genome-fasta-list")) {
+            let path = PathBuf::from(m.get_one::<String>(&id("genome-fasta-list")).unwrap());
+            let (reader, is_gzip) = open_genome_fasta_list(&path)?;
+            let relative_to_list = options.list_paths_relative_to_list
+                || flag_is_set(m, &id("genome-fasta-list-relative-to-list"));
+            let base = match relative_to_list && path.as_os_str() != STDIN_LIST_PATH {
+                true => Some(path.parent().unwrap_or_else(|| Path::new("")).to_path_buf()),
+                false => None,
+            };
+            GenomeFastaSource::List {
+                path,
+                lines: reader.lines(),
+                line_number: 0,
+                is_gzip,
+                base,
+            }
+        } else if m.contains_id(&id("genome-fasta-archive")) {
+            // Archives cannot be read lazily, as their members must be
+            // extracted before they can be used.
+            let extensions = normalise_genome_fasta_extensions(
+                &genome_fasta_extension_values(m, options),
+                options,
+            );
+            let mut paths = vec![];
+            for archive in m.get_many::<String>(&id("genome-fasta-archive")).unwrap() {
+                let (temporary_directory, archive_paths) =
+                    extract_genome_fasta_archive(Path::new(archive), |member| {
+                        has_genome_fasta_extension(member, &extensions, options, "archive member")
+                    })?;
+                paths.extend(archive_paths);
+                temporary_directories.push(temporary_directory);
+            }
+            GenomeFastaSource::Paths(paths.into_iter())
+        } else if m.contains_id(&id("single-genome-fasta")) {
+            let path = PathBuf::from(m.get_one::<String>(&id("single-genome-fasta")).unwrap());
+            GenomeFastaSource::Paths(vec![path].into_iter())
+        } else {
+            return Err(GenomeSpecificationError::NoSpecification);
+        };
+        let patterns: Vec<String> = match m.try_get_many::<String>(&id("genome-fasta-exclude")) {
+            Ok(Some(patterns)) => patterns.cloned().collect(),
+            _ => vec![],
+        };
+        Ok(GenomeFastaFileIterator {
+            source,
+            exclusions: ExclusionPatterns::new(&patterns)?,
+            options: options.clone(),
+            _temporary_directories: temporary_directories,
+        })
+    }
+
+    /// The next path from the source, before exclusion patterns are applied.
+    fn next_path(&mut self) -> Option<std::result::Result<PathBuf, GenomeSpecificationError>> {
+        let options = &self.options;
+        match &mut self.source {
+            GenomeFastaSource::Paths(paths) => paths.next().map(Ok),
+            GenomeFastaSource::Files {
+                values,
+                expanded,
+                extensions,
+                recursive,
+            } => loop {
+                let path = match expanded.next() {
+                    Some(path) => path,
+                    None => {
+                        let value = values.next()?;
+                        if is_glob_pattern(&value) {
+                            match expand_genome_fasta_glob(&value) {
+                                Ok(paths) => *expanded = paths.into_iter(),
+                                Err(e) => return Some(Err(e)),
+                            }
+                            continue;
+                        }
+                        PathBuf::from(value)
+                    }
+                };
+                if !path.is_dir() {
+                    return Some(Ok(path));
+                }
+                // Directories given as files are searched in full, in the
+                // same way as by parse_list_of_genome_fasta_files().
+                match genome_fasta_files_argument_directory(&path, extensions, *recursive, options)
+                {
+                    Ok(paths) => {
+                        let rest: Vec<PathBuf> = expanded.collect();
+                        *expanded = paths.into_iter().chain(rest).collect::<Vec<_>>().into_iter();
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            },
+            GenomeFastaSource::Directories {
+                dirs,
+                stack,
+                visited,
+                recursive,
+                extensions,
+            } => loop {
+                let entry = match stack.last_mut() {
+                    Some((dir, read_dir)) => match read_dir.next() {
+                        Some(Ok(entry)) => Some(entry),
+                        Some(Err(e)) => {
+                            match unreadable_directory_entry(
+                                dir,
+                                None,
+                                e,
+                                options.unreadable_entries,
+                            ) {
+                                Ok(()) => continue,
+                                Err(e) => return Some(Err(e)),
+                            }
+                        }
+                        None => None,
+                    },
+                    None => {
+                        let dir = dirs.next()?;
+                        match open_directory(&dir, *recursive, visited) {
+                            Ok(Some(read_dir)) => stack.push((dir, read_dir)),
+                            Ok(None) => {}
+                            Err(e) => return Some(Err(e)),
+                        }
+                        continue;
+                    }
+                };
+                let file = match entry {
+                    None => {
+                        stack.pop();
+                        continue;
+                    }
+                    Some(entry) => entry.path(),
+                };
+                if *recursive && file.is_dir() {
+                    match open_directory(&file, *recursive, visited) {
+                        Ok(Some(read_dir)) => stack.push((file, read_dir)),
+                        Ok(None) => {}
+                        Err(GenomeSpecificationError::UnreadableDirectory { source, .. }) => {
+                            // The parent is still on top of the stack.
+                            let dir = &stack.last().unwrap().0;
+                            if let Err(e) = unreadable_directory_entry(
+                                dir,
+                                Some(file),
+                                source,
+                                options.unreadable_entries,
+                            ) {
+                                return Some(Err(e));
+                            }
+                        }
+                        Err(e) => return Some(Err(e)),
+                    }
+                } else if !skip_hidden_file(&file, options)
+                    && has_genome_fasta_extension(&file, extensions, options, "directory entry")
+                {
+                    if file.exists() || !file.is_symlink() {
+                        return Some(Ok(file));
+                    }
+                    match options.broken_symlinks {
+                        BrokenSymlinkAction::Skip => warn!(
+                            "Not using broken symlink in genome-fasta-directory: {}",
+                            file.display()
+                        ),
+                        BrokenSymlinkAction::Fail => {
+                            return Some(Err(GenomeSpecificationError::BrokenSymlinks {
+                                links: vec![file],
+                            }))
+                        }
+                    }
+                }
+            },
+            GenomeFastaSource::List {
+                path,
+                lines,
+                line_number,
+                is_gzip,
+                base,
+            } => loop {
+                *line_number += 1;
+                let line = match lines.next()? {
+                    Ok(line) => line,
+                    Err(e) => {
+                        let e = GenomeSpecificationError::UnreadableListFile {
+                            path: path.clone(),
+                            line: Some(*line_number),
+                            source: e,
+                        };
+                        let e = match is_gzip {
+                            true => gzip_list_error(e),
+                            false => e,
+                        };
+                        // Reading again after an error may never finish.
+                        self.source = GenomeFastaSource::Finished;
+                        return Some(Err(e));
+                    }
+                };
+                if let Some((fasta_file, _)) = parse_genome_fasta_list_line(&line) {
+                    let path = fasta_file.path;
+                    return Some(Ok(match base {
+                        Some(base) if !path.is_absolute() && !is_remote_genome_path(&path) => {
+                            base.join(path)
+                        }
+                        _ => path,
+                    }));
+                }
+            },
+            GenomeFastaSource::Finished => None,
+        }
+    }
+}
+
+impl Iterator for GenomeFastaFileIterator {
+    type Item = std::result::Result<PathBuf, GenomeSpecificationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_path()? {
+                Ok(path) if self.exclusions.excludes(&path) => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+/// Start reading dir, or return None if it has already been visited during a
+/// recursive search.
+fn open_directory(
+    dir: &Path,
+    recursive: bool,
+    visited: &mut HashSet<PathBuf>,
+) -> std::result::Result<Option<ReadDir>, GenomeSpecificationError> {
+    let unreadable = |e| GenomeSpecificationError::UnreadableDirectory {
+        dir: dir.to_path_buf(),
+        source: e,
+    };
+    if recursive {
+        let canonical = std::fs::canonicalize(dir).map_err(unreadable)?;
+        if !visited.insert(canonical) {
+            debug!("Not descending into already visited directory '{}'", dir.display());
+            return Ok(None);
+        }
+    }
+    std::fs::read_dir(dir).map(Some).map_err(unreadable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genome_fasta_list_relative_to_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        let absolute = dir.path().join("c.fna");
+        let list = sub.join("genomes.txt");
+        std::fs::write(&list, format!("a.fna\n../b.fna\n{}\n", absolute.display())).unwrap();
+        let list_str = list.to_str().unwrap();
+        let paths = |args: &[&str]| -> Vec<PathBuf> {
+            let m = crate::clap_utils::add_genome_specification_arguments(clap::Command::new("t"))
+                .try_get_matches_from(std::iter::once("t").chain(args.iter().copied()))
+                .unwrap();
+            GenomeFastaFileIterator::new(&m)
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        assert_eq!(
+            vec![PathBuf::from("a.fna"), PathBuf::from("../b.fna"), absolute.clone()],
+            paths(&["--genome-fasta-list", list_str])
+        );
+        assert_eq!(
+            vec![sub.join("a.fna"), sub.join("../b.fna"), absolute],
+            paths(&[
+                "--genome-fasta-list",
+                list_str,
+                "--genome-fasta-list-relative-to-list"
+            ])
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// First line of every genome directory manifest, identifying the format.
+const MANIFEST_HEADER: &str = "# bird_tool_utils genome directory manifest version 1";
+
+/// Where the manifests recording the genomes found in genome FASTA directories
+/// are kept. See GenomeSpecificationOptions::directory_manifests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestLocation {
+    /// A hidden file next to each directory, e.g. '.bins.genome_manifest.tsv'
+    /// for the directory 'bins'.
+    NextToDirectory,
+    /// Files in the given directory, named after the path of each directory.
+    InDirectory(PathBuf),
+}
+
+impl ManifestLocation {
+    /// The path of the manifest for the genome FASTA directory dir.
+    pub fn manifest_path(&self, dir: &Path) -> PathBuf {
+        let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        match self {
+            ManifestLocation::NextToDirectory => {
+                let name = dir
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                dir.with_file_name(format!(".{}.genome_manifest.tsv", name))
+            }
+            ManifestLocation::InDirectory(manifest_dir) => {
+                let name: String = dir
+                    .to_string_lossy()
+                    .chars()
+                    .map(|c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                        true => c,
+                        false => '_',
+                    })
+                    .collect();
+                manifest_dir.join(format!("{}.genome_manifest.tsv", name))
+            }
+        }
+    }
+}
+
+/// The genome FASTA files found in a directory, along with what is needed to
+/// tell whether the directory has changed since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenomeDirectoryManifest {
+    /// Description of the settings the directory was searched with. A
+    /// manifest made with different settings is out of date.
+    pub settings: String,
+    /// Each directory searched, with its modification time.
+    pub directories: Vec<(PathBuf, Duration)>,
+    /// The genome FASTA files found, with paths relative to the directory.
+    pub genomes: Vec<ManifestEntry>,
+}
+
+/// A genome FASTA file recorded in a GenomeDirectoryManifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    /// Modification time, or zero if the file could not be read.
+    pub modified: Duration,
+    /// Size in bytes, or zero if the file could not be read.
+    pub size: u64,
+}
+
+impl GenomeDirectoryManifest {
+    /// Write the manifest as tab-separated lines, one per directory and genome.
+    /// Returns an Err if a path is not valid UTF-8 or contains a tab or
+    /// newline, as it could not be read back.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", MANIFEST_HEADER)?;
+        writeln!(writer, "settings\t{}", self.settings)?;
+        for (dir, modified) in &self.directories {
+            writeln!(
+                writer,
+                "directory\t{}\t{}",
+                manifest_path_string(dir)?,
+                format_time(modified)
+            )?;
+        }
+        for genome in &self.genomes {
+            writeln!(
+                writer,
+                "genome\t{}\t{}\t{}",
+                manifest_path_string(&genome.path)?,
+                format_time(&genome.modified),
+                genome.size
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Read a manifest written by write(). Returns an Err describing the
+    /// problem if it is not a valid manifest.
+    pub fn read<R: BufRead>(reader: R) -> std::result::Result<Self, String> {
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(Ok(line)) if line == MANIFEST_HEADER => {}
+            Some(Err(e)) => return Err(e.to_string()),
+            _ => return Err("missing manifest header".to_string()),
+        }
+        let mut manifest = GenomeDirectoryManifest {
+            settings: String::new(),
+            directories: vec![],
+            genomes: vec![],
+        };
+        let mut found_settings = false;
+        for (i, line) in lines.enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            // Line numbers are 1-based, after the header
+            let invalid = || format!("invalid line {}", i + 2);
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["settings", settings] => {
+                    manifest.settings = settings.to_string();
+                    found_settings = true;
+                }
+                ["directory", path, modified] => manifest
+                    .directories
+                    .push((PathBuf::from(path), parse_time(modified).ok_or_else(invalid)?)),
+                ["genome", path, modified, size] => manifest.genomes.push(ManifestEntry {
+                    path: PathBuf::from(path),
+                    modified: parse_time(modified).ok_or_else(invalid)?,
+                    size: size.parse().map_err(|_| invalid())?,
+                }),
+                _ => return Err(invalid()),
+            }
+        }
+        match found_settings {
+            true => Ok(manifest),
+            false => Err("missing settings line".to_string()),
+        }
+    }
+
+    /// Whether every directory recorded still has the same modification time,
+    /// meaning no files have been added to or removed from them since.
+    pub fn is_current(&self) -> bool {
+        self.directories
+            .iter()
+            .all(|(dir, modified)| modification_time(dir).as_ref() == Some(modified))
+    }
+}
+
+/// Read the manifest at manifest_path for the genome FASTA directory dir,
+/// returning the genome FASTA files it records, or None if the manifest does
+/// not exist, is corrupt, or is out of date. The reason a manifest cannot be
+/// used is logged.
+pub fn read_current_manifest(manifest_path: &Path, dir: &Path, settings: &str) -> Option<Vec<PathBuf>> {
+    let file = match File::open(manifest_path) {
+        Ok(file) => file,
+        Err(e) => {
+            debug!(
+                "Not using genome manifest '{}' as it could not be opened: {}",
+                manifest_path.display(),
+                e
+            );
+            return None;
+        }
+    };
+    match GenomeDirectoryManifest::read(BufReader::new(file)) {
+        Ok(manifest) => {
+            if manifest.settings != settings {
+                info!(
+                    "Regenerating genome manifest '{}' as the directory is being searched with different settings",
+                    manifest_path.display()
+                );
+                None
+            } else if !manifest.is_current() {
+                info!(
+                    "Regenerating genome manifest '{}' as the directory has changed",
+                    manifest_path.display()
+                );
+                None
+            } else {
+                info!(
+                    "Using {} genome(s) recorded in manifest '{}' for directory '{}'",
+                    manifest.genomes.len(),
+                    manifest_path.display(),
+                    dir.display()
+                );
+                Some(manifest.genomes.into_iter().map(|g| dir.join(g.path)).collect())
+            }
+        }
+        Err(e) => {
+            info!(
+                "Regenerating genome manifest '{}' as it is corrupt: {}",
+                manifest_path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Write a manifest to manifest_path recording that the genome FASTA directory
+/// dir contained genomes, after searching directories (which should include
+/// dir itself) with settings. Failure to write the manifest is logged but is
+/// not an error, as the directory can still be searched next time.
+pub fn write_manifest(
+    manifest_path: &Path,
+    dir: &Path,
+    settings: &str,
+    directories: &[PathBuf],
+    genomes: &[PathBuf],
+) {
+    let mut manifest = GenomeDirectoryManifest {
+        settings: settings.to_string(),
+        directories: vec![],
+        genomes: vec![],
+    };
+    for d in directories {
+        match modification_time(d) {
+            Some(modified) => manifest.directories.push((d.clone(), modified)),
+            None => {
+                warn!(
+                    "Not writing genome manifest '{}' as the modification time of '{}' could not be read",
+                    manifest_path.display(),
+                    d.display()
+                );
+                return;
+            }
+        }
+    }
+    for genome in genomes {
+        let metadata = std::fs::metadata(genome).ok();
+        manifest.genomes.push(ManifestEntry {
+            path: genome.strip_prefix(dir).unwrap_or(genome).to_path_buf(),
+            modified: metadata
+                .as_ref()
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default(),
+            size: metadata.map(|m| m.len()).unwrap_or(0),
+        });
+    }
+    // Write to a temporary file first, so that a manifest which is only
+    // partially written is never read.
+    let mut temporary_path = manifest_path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+    let result = File::create(&temporary_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        manifest.write(&mut writer)?;
+        writer.flush()?;
+        std::fs::rename(&temporary_path, manifest_path)
+    });
+    match result {
+        Ok(()) => info!(
+            "Wrote genome manifest '{}' recording {} genome(s)",
+            manifest_path.display(),
+            genomes.len()
+        ),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary_path);
+            warn!(
+                "Failed to write genome manifest '{}': {}",
+                manifest_path.display(),
+                e
+            )
+        }
+    }
+}
+
+fn modification_time(path: &Path) -> Option<Duration> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t: SystemTime| t.duration_since(UNIX_EPOCH).ok())
+}
+
+fn manifest_path_string(path: &Path) -> io::Result<&str> {
+    match path.to_str() {
+        Some(s) if !s.contains(['\t', '\n', '\r']) => Ok(s),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the path '{}' cannot be recorded in a manifest",
+                path.to_string_lossy()
+            ),
+        )),
+    }
+}
+
+fn format_time(time: &Duration) -> String {
+    format!("{}.{:09}", time.as_secs(), time.subsec_nanos())
+}
+
+fn parse_time(s: &str) -> Option<Duration> {
+    let (secs, nanos) = s.split_once('.')?;
+    Some(Duration::new(secs.parse().ok()?, nanos.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_write_read_round_trip() {
+        let manifest = GenomeDirectoryManifest {
+            settings: "extensions=fna;recursive=true".to_string(),
+            directories: vec![
+                (PathBuf::from("/data/bins"), Duration::new(1700000000, 123)),
+                (PathBuf::from("/data/bins/sub"), Duration::new(1700000001, 0)),
+            ],
+            genomes: vec![
+                ManifestEntry {
+                    path: PathBuf::from("a.fna"),
+                    modified: Duration::new(1700000002, 999_999_999),
+                    size: 1234,
+                },
+                ManifestEntry {
+                    path: PathBuf::from("sub/b.fna"),
+                    modified: Duration::default(),
+                    size: 0,
+                },
+            ],
+        };
+        let mut written = vec![];
+        manifest.write(&mut written).unwrap();
+        assert_eq!(manifest, GenomeDirectoryManifest::read(&written[..]).unwrap());
+
+        let mut unwritable = manifest.clone();
+        unwritable.genomes[0].path = PathBuf::from("a\tb.fna");
+        assert!(unwritable.write(&mut vec![]).is_err());
+    }
+}
@@ -0,0 +1,1623 @@
+use std;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::genome_download::is_remote_genome_path;
+use crate::genome_manifest::ManifestLocation;
+
+/// Genome FASTA list path meaning the list should be read from stdin.
+pub const STDIN_LIST_PATH: &str = "-";
+
+/// Most messages logged about files not used as genome FASTA files, e.g.
+/// because of their extension, before further messages are suppressed. See
+/// logging::log_limited!().
+pub const SKIPPED_GENOME_MESSAGE_LIMIT: usize = 100;
+pub(crate) const SKIPPED_GENOME_MESSAGE_KEY: &str = "Not using ... as a genome FASTA file";
+
+/// The first two bytes of every gzip file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Suffixes of the compression formats genome FASTA files are commonly stored
+/// in, without the leading dot.
+pub const COMPRESSION_SUFFIXES: &[&str] = &["gz", "bz2", "zst"];
+
+/// Whether a genome FASTA path appears to be compressed, judging by whether its
+/// file name ends in one of COMPRESSION_SUFFIXES. Useful for deciding whether a
+/// path returned by parse_list_of_genome_fasta_files needs decompressing.
+pub fn is_compressed_genome_fasta_path<P: AsRef<Path>>(path: P) -> bool {
+    match path.as_ref().file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            COMPRESSION_SUFFIXES
+                .iter()
+                .any(|c| file_name_has_extension(&name, c))
+        }
+        None => false,
+    }
+}
+
+/// Value of --genome-fasta-extension meaning the extension should be detected
+/// from the files present.
+pub const AUTO_GENOME_FASTA_EXTENSION: &str = "auto";
+
+/// Extensions which are looked for when the genome FASTA extension is
+/// AUTO_GENOME_FASTA_EXTENSION.
+pub const AUTO_DETECTED_EXTENSIONS: &[&str] =
+    &["fna", "fa", "fasta", "fna.gz", "fa.gz", "fasta.gz"];
+
+/// The extension of file_name, for reporting which extensions are present. If
+/// the last suffix is one of COMPRESSION_SUFFIXES then the suffix before it is
+/// included too e.g. 'fna.gz'.
+pub(crate) fn extension_of_file_name(file_name: &str) -> Option<String> {
+    let mut parts = file_name.rsplitn(3, '.');
+    let last = parts.next()?;
+    let second = parts.next()?;
+    match COMPRESSION_SUFFIXES.contains(&last) && parts.next().is_some() {
+        true => Some(format!("{}.{}", second, last)),
+        false => match second.is_empty() {
+            // A dotfile such as '.bashrc' has no extension.
+            true => None,
+            false => Some(last.to_string()),
+        },
+    }
+}
+
+/// Whether file_name ends with '.' followed by extension. The whole suffix is
+/// compared, so compound extensions such as 'fna.gz' work, and there must be
+/// something before the suffix for it to count.
+pub(crate) fn file_name_has_extension(file_name: &str, extension: &str) -> bool {
+    file_name.len() > extension.len() + 1
+        && file_name.ends_with(extension)
+        && file_name[..file_name.len() - extension.len()].ends_with('.')
+}
+
+/// Options controlling how genome FASTA files are found and checked, for use
+/// with e.g. clap_utils::parse_list_of_genome_fasta_paths_with_options().
+#[derive(Debug, Clone, Default)]
+pub struct GenomeSpecificationOptions {
+    /// Return an Err if no genomes were found.
+    pub fail_on_no_genomes: bool,
+    /// Return an Err if fewer than this many genomes were found, e.g. 2 for
+    /// tools which compare genomes. fail_on_no_genomes is equivalent to 1.
+    pub min_genomes: usize,
+    /// How thoroughly to check each genome FASTA file before returning it.
+    pub validation: ValidationLevel,
+    /// Match --genome-fasta-extension case-sensitively, so that e.g. '.FNA'
+    /// files are not used when the extension is 'fna'. By default matching is
+    /// case-insensitive.
+    pub case_sensitive_extensions: bool,
+    /// What to do with symlinks in a genome FASTA directory whose targets do
+    /// not exist. Symlinks are otherwise followed, and whether they match the
+    /// extension is decided by the name of the link, not of its target.
+    pub broken_symlinks: BrokenSymlinkAction,
+    /// What to do when an entry of a genome FASTA directory cannot be read,
+    /// e.g. a subdirectory without read permission during a recursive search.
+    /// A genome FASTA directory which cannot be read at all is always an Err.
+    pub unreadable_entries: UnreadableEntryAction,
+    /// What to do when a path given to --genome-fasta-files is a directory.
+    pub directories_in_files: DirectoryInFilesAction,
+    /// What to do with genome FASTA files which are empty. They are checked
+    /// for unless this is EmptyFileAction::Ignore, whatever the validation
+    /// level.
+    pub empty_files: EmptyFileAction,
+    /// Maximum number of threads used to check genome FASTA files. Checks are
+    /// only parallelised for large numbers of genomes (see
+    /// parallel_validation_threshold), and 0 or 1 means no parallelism.
+    pub threads: usize,
+    /// Number of genomes above which checks are spread across threads. None
+    /// means PARALLEL_VALIDATION_THRESHOLD.
+    pub parallel_validation_threshold: Option<usize>,
+    /// Return an Err if two genomes would have the same genome name (see
+    /// check_genome_names_are_unique()).
+    pub check_genome_names: bool,
+    /// Return an Err if more than this many genomes are specified. When
+    /// searching a directory, this is checked while the directory is being
+    /// walked, so that enormous directories are not enumerated in full. The
+    /// hidden --max-genomes argument overrides this.
+    pub max_genomes: Option<usize>,
+    /// What to do when the genome FASTA extension is 'auto' and files with
+    /// more than one of AUTO_DETECTED_EXTENSIONS are present.
+    pub multiple_auto_extensions: MultipleExtensionAction,
+    /// Return canonical (absolute, with symlinks resolved) paths, removing any
+    /// duplicates that result. See canonicalize_genome_fasta_files(). By
+    /// default paths are returned as the user gave them, and only paths which
+    /// are exactly repeated are removed (see deduplicate_genome_fasta_files()).
+    pub canonicalize_paths: bool,
+    /// Use files in genome FASTA directories whose names start with '.', and
+    /// search such subdirectories when searching recursively. By default they
+    /// are skipped, as they are usually not genomes, e.g. '.DS_Store' files and
+    /// '._genome.fna' AppleDouble files made by macOS, or '.snakemake'
+    /// directories.
+    pub include_hidden_files: bool,
+    /// Download genome FASTA files given as http://, https:// or ftp:// URLs
+    /// e.g. in a genome FASTA list. Requires the remote-genomes feature. See
+    /// genome_download::download_remote_genome_fasta_files().
+    pub allow_remote: bool,
+    /// Record the genome FASTA files found in each genome FASTA directory in a
+    /// manifest at this location, and use it instead of searching the
+    /// directory again for as long as the directory is unchanged. See
+    /// genome_manifest::read_current_manifest().
+    pub directory_manifests: Option<ManifestLocation>,
+    /// Log the number and total size of the genome FASTA files found. See
+    /// summarize_genome_fasta_files().
+    pub log_summary: bool,
+    /// Resolve relative paths in a genome FASTA list against the directory
+    /// containing the list, rather than the current working directory. See
+    /// resolve_genome_fasta_list_paths().
+    pub list_paths_relative_to_list: bool,
+    /// Log progress after this many entries of a genome FASTA directory have
+    /// been looked at, and after each further this many. None means
+    /// genome_directory::DEFAULT_PROGRESS_INTERVAL, and Some(0) means no
+    /// progress is logged.
+    pub progress_interval: Option<usize>,
+    /// Parse the arguments added by
+    /// clap_utils::add_fasta_specification_arguments() with this prefix, e.g.
+    /// --reference-fasta-files for the prefix 'reference'. None means the
+    /// usual genome arguments e.g. --genome-fasta-files.
+    pub argument_prefix: Option<String>,
+}
+
+impl GenomeSpecificationOptions {
+    /// The clap argument id of the genome argument name e.g.
+    /// 'genome-fasta-files', taking argument_prefix into account.
+    pub fn argument_id(&self, name: &str) -> String {
+        match &self.argument_prefix {
+            Some(prefix) => prefixed_argument_id(name, prefix),
+            None => name.to_string(),
+        }
+    }
+
+    /// The minimum number of genomes which must be found, taking both
+    /// fail_on_no_genomes and min_genomes into account.
+    pub fn required_genomes(&self) -> usize {
+        match self.fail_on_no_genomes {
+            true => std::cmp::max(self.min_genomes, 1),
+            false => self.min_genomes,
+        }
+    }
+}
+
+/// Prefix of the arguments added by
+/// clap_utils::add_genome_specification_arguments().
+pub const GENOME_ARGUMENT_PREFIX: &str = "genome";
+
+/// The id of the genome argument name e.g. 'genome-fasta-files' when added
+/// with prefix instead of GENOME_ARGUMENT_PREFIX e.g. 'reference-fasta-files'.
+pub fn prefixed_argument_id(name: &str, prefix: &str) -> String {
+    name.replacen(GENOME_ARGUMENT_PREFIX, prefix, 1)
+}
+
+/// What to do when more than one genome FASTA extension is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MultipleExtensionAction {
+    /// Use files with any of the detected extensions.
+    #[default]
+    UseAll,
+    /// Return an Err asking the user to specify the extension.
+    Fail,
+}
+
+/// What to do when a genome FASTA file is found to be empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyFileAction {
+    /// Log a warning listing the empty files.
+    #[default]
+    Warn,
+    /// Return an Err listing the empty files.
+    Fail,
+    /// Do not check for empty files, which avoids looking at the metadata of
+    /// each file.
+    Ignore,
+}
+
+/// What to do when a broken symlink is found while searching a directory for
+/// genome FASTA files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BrokenSymlinkAction {
+    /// Leave broken symlinks out of the results, with a warning.
+    #[default]
+    Skip,
+    /// Return an Err listing all broken symlinks.
+    Fail,
+}
+
+/// What to do when an entry of a genome FASTA directory cannot be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnreadableEntryAction {
+    /// Leave the entry out of the results, with a warning.
+    #[default]
+    Skip,
+    /// Return an Err naming the entry.
+    Fail,
+}
+
+/// What to do when a directory is given to --genome-fasta-files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DirectoryInFilesAction {
+    /// Use the genome FASTA files in the directory, found as if it had been
+    /// given to --genome-fasta-directory.
+    #[default]
+    Expand,
+    /// Return an Err suggesting --genome-fasta-directory be used instead.
+    Fail,
+}
+
+/// How thoroughly genome FASTA files are checked before being returned. Each
+/// level includes the checks of the levels before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ValidationLevel {
+    /// No checks are made.
+    #[default]
+    None,
+    /// Each file must exist, be a regular file (or a symlink to one) and be
+    /// readable. See check_genome_fasta_files_exist().
+    Exists,
+    /// Each file must start with a FASTA header. See validate_fasta_contents().
+    FastaHeader,
+    /// As FastaHeader, and the first record must look like nucleotide rather
+    /// than amino acid sequence.
+    NucleotideFasta,
+}
+
+/// Errors which can occur when working out which genome FASTA files were
+/// specified by the user.
+#[derive(Debug)]
+pub enum GenomeSpecificationError {
+    /// None of the genome specification arguments were given.
+    NoSpecification,
+    /// The genome FASTA directories contained no files with a matching
+    /// extension. extensions_present are the extensions of the files which
+    /// were there.
+    EmptyDirectory {
+        dirs: Vec<PathBuf>,
+        extensions_present: Vec<String>,
+    },
+    /// The genome FASTA extension was to be detected automatically, but files
+    /// with several extensions were found. Each entry is an extension and the
+    /// number of files with it.
+    AmbiguousExtension { counts: Vec<(String, usize)> },
+    /// The genome FASTA directory (or one of its entries) could not be read.
+    UnreadableDirectory { dir: PathBuf, source: io::Error },
+    /// An entry of a genome FASTA directory could not be read. The entry is
+    /// None if the failure happened before its name was known.
+    UnreadableDirectoryEntry {
+        dir: PathBuf,
+        entry: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// The genome FASTA list file could not be opened, or could not be read at
+    /// the given (1-based) line.
+    UnreadableListFile {
+        path: PathBuf,
+        line: Option<usize>,
+        source: io::Error,
+    },
+    /// The genome FASTA list appeared to be gzip compressed, but could not be
+    /// decompressed.
+    CorruptGzipListFile { path: PathBuf, source: io::Error },
+    /// The same genome name or path appears on more than one line of a genome
+    /// FASTA list which gives explicit genome names.
+    DuplicateListEntries { path: PathBuf, problems: Vec<String> },
+    /// The genome FASTA list file contained no paths, once blank lines and
+    /// comments were removed.
+    EmptyListFile { path: PathBuf },
+    /// A glob pattern given as a genome FASTA file or exclusion could not be
+    /// parsed.
+    InvalidGlobPattern { pattern: String, message: String },
+    /// A glob pattern given as a genome FASTA file matched no files.
+    UnmatchedGlobPattern { pattern: String },
+    /// A response file given to --genome-fasta-files as '@path' could not be
+    /// read.
+    UnreadableResponseFile { path: PathBuf, source: io::Error },
+    /// A path given to --genome-fasta-files is a directory.
+    DirectoryInGenomeFastaFiles { path: PathBuf },
+    /// More than one genome FASTA file has the same name once directories and
+    /// extensions are removed. Each entry is a name and the paths sharing it.
+    DuplicateGenomeNames { duplicates: Vec<(String, Vec<PathBuf>)> },
+    /// Genome FASTA files which are missing or cannot be read, each with the
+    /// reason why.
+    UnusableGenomeFiles { files: Vec<(PathBuf, String)> },
+    /// Genome FASTA files which are zero bytes long.
+    EmptyGenomeFiles { files: Vec<PathBuf> },
+    /// Symlinks in the genome FASTA directory whose targets do not exist.
+    BrokenSymlinks { links: Vec<PathBuf> },
+    /// Genomes were found, but every one was removed by an exclusion pattern.
+    AllGenomesExcluded { patterns: Vec<String> },
+    /// Fewer than min_genomes genome FASTA files were found with the given
+    /// genome specification argument e.g. '--genome-fasta-directory'.
+    TooFewGenomes {
+        specification: String,
+        min_genomes: usize,
+        found: usize,
+    },
+    /// More genomes were specified than the maximum allowed. found is the
+    /// number found before giving up, which may be fewer than were specified.
+    TooManyGenomes { max_genomes: usize, found: usize },
+    /// A genome FASTA file given as a URL could not be downloaded.
+    DownloadFailed { url: String, message: String },
+    /// The genome FASTA archive could not be opened or extracted.
+    UnreadableArchive { archive: PathBuf, source: io::Error },
+    /// The genome FASTA archives contained no members with a matching
+    /// extension.
+    EmptyArchive { archives: Vec<PathBuf> },
+    /// Genomes were specified in a way which needs temporary files, e.g.
+    /// '--genome-fasta-archive' or 'URLs', but were parsed by a function which
+    /// cannot return the temporary directories for them to be deleted.
+    TemporaryFilesNotSupported { specification: String },
+}
+
+impl fmt::Display for GenomeSpecificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenomeSpecificationError::NoSpecification => {
+                write!(
+                    f,
+                    "No genome specification options specified. Genomes can be \
+                    specified with --genome-fasta-files, --genome-fasta-directory, \
+                    --genome-fasta-list or --genome-fasta-archive"
+                )
+            }
+            GenomeSpecificationError::EmptyDirectory {
+                dirs,
+                extensions_present,
+            } => {
+                write!(
+                    f,
+                    "Found 0 genomes from the genome-fasta-directory {}, cannot continue.",
+                    dirs.iter()
+                        .map(|d| format!("'{}'", d.display()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )?;
+                if !extensions_present.is_empty() {
+                    write!(
+                        f,
+                        " The extension(s) of files present are {}.",
+                        extensions_present
+                            .iter()
+                            .map(|e| format!("'{}'", e))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
+                }
+                Ok(())
+            }
+            GenomeSpecificationError::AmbiguousExtension { counts } => write!(
+                f,
+                "Genome FASTA files with more than one extension were found ({}), \
+                please specify which to use with --genome-fasta-extension.",
+                counts
+                    .iter()
+                    .map(|(e, n)| format!("{} '{}' file(s)", n, e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            GenomeSpecificationError::UnreadableDirectory { dir, source } => write!(
+                f,
+                "Failed to read genome-fasta-directory '{}': {}",
+                dir.display(),
+                source
+            ),
+            GenomeSpecificationError::UnreadableDirectoryEntry {
+                dir,
+                entry: Some(entry),
+                source,
+            } => write!(
+                f,
+                "Failed to read entry '{}' of genome-fasta-directory '{}': {}",
+                entry.display(),
+                dir.display(),
+                source
+            ),
+            GenomeSpecificationError::UnreadableDirectoryEntry {
+                dir,
+                entry: None,
+                source,
+            } => write!(
+                f,
+                "Failed to read an entry of genome-fasta-directory '{}': {}",
+                dir.display(),
+                source
+            ),
+            GenomeSpecificationError::UnreadableListFile {
+                path,
+                line: None,
+                source,
+            } => write!(
+                f,
+                "Failed to open genome fasta list {}: {}",
+                describe_list_path(path),
+                source
+            ),
+            GenomeSpecificationError::UnreadableListFile {
+                path,
+                line: Some(line),
+                source,
+            } => write!(
+                f,
+                "Error when reading genome fasta list {} on line {}: {}",
+                describe_list_path(path),
+                line,
+                source
+            ),
+            GenomeSpecificationError::CorruptGzipListFile { path, source } => write!(
+                f,
+                "The genome fasta list {} appears to be gzip compressed, but is corrupt: {}",
+                describe_list_path(path),
+                source
+            ),
+            GenomeSpecificationError::DuplicateListEntries { path, problems } => write!(
+                f,
+                "Duplicate entries in genome fasta list {}: {}",
+                describe_list_path(path),
+                problems.join("; ")
+            ),
+            GenomeSpecificationError::EmptyListFile { path } => {
+                write!(
+                    f,
+                    "Found 0 genomes in the genome fasta list {}, cannot continue.",
+                    describe_list_path(path)
+                )?;
+                if path.as_os_str() == STDIN_LIST_PATH {
+                    write!(f, " Was the list of genome paths piped in?")?;
+                }
+                Ok(())
+            }
+            GenomeSpecificationError::InvalidGlobPattern { pattern, message } => write!(
+                f,
+                "Failed to parse glob pattern '{}': {}",
+                pattern, message
+            ),
+            GenomeSpecificationError::UnmatchedGlobPattern { pattern } => write!(
+                f,
+                "The pattern '{}' given to --genome-fasta-files did not match any files",
+                pattern
+            ),
+            GenomeSpecificationError::UnreadableResponseFile { path, source } => write!(
+                f,
+                "Failed to read response file '{}' given to --genome-fasta-files \
+                 as '@{}': {}",
+                path.display(),
+                path.display(),
+                source
+            ),
+            GenomeSpecificationError::DirectoryInGenomeFastaFiles { path } => write!(
+                f,
+                "The path '{}' given to --genome-fasta-files is a directory. Use \
+                 --genome-fasta-directory to use the genome FASTA files in a directory",
+                path.display()
+            ),
+            GenomeSpecificationError::DuplicateGenomeNames { duplicates } => {
+                write!(
+                    f,
+                    "Multiple genome FASTA files have the same name once directories \
+                    and extensions are removed, so genome names would collide:"
+                )?;
+                for (name, paths) in duplicates {
+                    write!(
+                        f,
+                        " '{}' ({})",
+                        name,
+                        paths
+                            .iter()
+                            .map(|p| p.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
+                }
+                Ok(())
+            }
+            GenomeSpecificationError::UnusableGenomeFiles { files } => {
+                write!(f, "{} genome FASTA file(s) cannot be used:", files.len())?;
+                for (path, reason) in files {
+                    write!(f, "\n  {}: {}", path.display(), reason)?;
+                }
+                Ok(())
+            }
+            GenomeSpecificationError::AllGenomesExcluded { patterns } => write!(
+                f,
+                "All genomes were excluded by the exclusion pattern(s) {}, cannot continue.",
+                patterns
+                    .iter()
+                    .map(|p| format!("'{}'", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            GenomeSpecificationError::EmptyGenomeFiles { files } => write!(
+                f,
+                "{} genome FASTA file(s) are empty: {}",
+                files.len(),
+                files
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            GenomeSpecificationError::BrokenSymlinks { links } => write!(
+                f,
+                "Found {} broken symlink(s) in the genome-fasta-directory: {}",
+                links.len(),
+                links
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            GenomeSpecificationError::TooFewGenomes {
+                specification,
+                min_genomes,
+                found,
+            } => write!(
+                f,
+                "Found {} with {}, but at least {} are required",
+                match found {
+                    0 => "no genomes".to_string(),
+                    1 => "only 1 genome".to_string(),
+                    n => format!("only {} genomes", n),
+                },
+                specification,
+                min_genomes
+            ),
+            GenomeSpecificationError::TooManyGenomes { max_genomes, found } => write!(
+                f,
+                "Found at least {} genomes, more than the maximum of {}, so not \
+                continuing. Use --genome-fasta-list to specify genomes explicitly.",
+                found, max_genomes
+            ),
+            GenomeSpecificationError::DownloadFailed { url, message } => write!(
+                f,
+                "Failed to download genome FASTA file from '{}': {}",
+                url, message
+            ),
+            GenomeSpecificationError::UnreadableArchive { archive, source } => write!(
+                f,
+                "Failed to extract genome-fasta-archive '{}': {}",
+                archive.display(),
+                source
+            ),
+            GenomeSpecificationError::EmptyArchive { archives } => write!(
+                f,
+                "Found 0 genomes in the genome-fasta-archive {}, cannot continue.",
+                archives
+                    .iter()
+                    .map(|a| format!("'{}'", a.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            GenomeSpecificationError::TemporaryFilesNotSupported { specification } => write!(
+                f,
+                "Genomes given as {} are extracted or downloaded into temporary files, \
+                which this tool is not able to delete afterwards, so they cannot be used. \
+                Tools can support them by parsing genomes with \
+                parse_genome_fasta_files_with_options(), which returns a \
+                ParsedGenomeFastaFiles.",
+                specification
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenomeSpecificationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GenomeSpecificationError::UnreadableDirectory { source, .. } => Some(source),
+            GenomeSpecificationError::UnreadableDirectoryEntry { source, .. } => Some(source),
+            GenomeSpecificationError::UnreadableListFile { source, .. } => Some(source),
+            GenomeSpecificationError::UnreadableResponseFile { source, .. } => Some(source),
+            GenomeSpecificationError::CorruptGzipListFile { source, .. } => Some(source),
+            GenomeSpecificationError::UnreadableArchive { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn describe_list_path(path: &Path) -> String {
+    match path.as_os_str() == STDIN_LIST_PATH {
+        true => "read from stdin".to_string(),
+        false => format!("file {}", path.display()),
+    }
+}
+
+/// Read the genome FASTA files listed in the file at path, or from stdin if
+/// path is "-". See read_genome_fasta_list() for the format. Lists which are
+/// gzip compressed are decompressed transparently.
+pub fn read_genome_fasta_list_file(
+    path: &Path,
+) -> std::result::Result<Vec<GenomeFastaFile>, GenomeSpecificationError> {
+    let (reader, is_gzip) = open_genome_fasta_list(path)?;
+    read_genome_fasta_list(reader, path).map_err(|e| match is_gzip {
+        true => gzip_list_error(e),
+        false => e,
+    })
+}
+
+/// Open the genome FASTA list at path, or stdin if path is "-", returning a
+/// reader of its lines, decompressing it if it starts with the gzip magic
+/// bytes, along with whether it was gzip compressed.
+pub(crate) fn open_genome_fasta_list(
+    path: &Path,
+) -> std::result::Result<(Box<dyn BufRead>, bool), GenomeSpecificationError> {
+    let unreadable = |e| GenomeSpecificationError::UnreadableListFile {
+        path: path.to_path_buf(),
+        line: None,
+        source: e,
+    };
+    let mut reader: Box<dyn BufRead> = if path.as_os_str() == STDIN_LIST_PATH {
+        let stdin = io::stdin();
+        if stdin.is_terminal() {
+            warn!(
+                "Reading the genome fasta list from stdin, which is a terminal. \
+                End the list with Ctrl-D."
+            );
+        }
+        Box::new(stdin.lock())
+    } else {
+        Box::new(BufReader::new(File::open(path).map_err(unreadable)?))
+    };
+    let is_gzip = reader.fill_buf().map_err(unreadable)?.starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        debug!("Decompressing gzipped genome fasta list {}", describe_list_path(path));
+        reader = Box::new(BufReader::new(MultiGzDecoder::new(reader)));
+    }
+    Ok((reader, is_gzip))
+}
+
+/// Convert an error reading a line of a gzip compressed genome FASTA list into
+/// one saying the list is corrupt, as that is the likely cause.
+pub(crate) fn gzip_list_error(e: GenomeSpecificationError) -> GenomeSpecificationError {
+    match e {
+        GenomeSpecificationError::UnreadableListFile {
+            path,
+            line: Some(_),
+            source,
+        } => GenomeSpecificationError::CorruptGzipListFile { path, source },
+        e => e,
+    }
+}
+
+/// Make the relative paths of genome FASTA files read from the genome FASTA
+/// list at list_path relative to the directory containing the list, rather
+/// than the current working directory. Absolute paths and URLs are unchanged,
+/// as are all paths if the list was read from stdin.
+pub fn resolve_genome_fasta_list_paths(
+    files: Vec<GenomeFastaFile>,
+    list_path: &Path,
+) -> Vec<GenomeFastaFile> {
+    if list_path.as_os_str() == STDIN_LIST_PATH {
+        debug!(
+            "Not resolving genome fasta list paths relative to the list, as it \
+            was read from stdin"
+        );
+        return files;
+    }
+    let base = list_path.parent().unwrap_or_else(|| Path::new(""));
+    debug!(
+        "Resolving relative paths in genome fasta list {} against the directory '{}'",
+        describe_list_path(list_path),
+        base.display()
+    );
+    files
+        .into_iter()
+        .map(|file| match file.path.is_absolute() || is_remote_genome_path(&file.path) {
+            true => file,
+            false => GenomeFastaFile {
+                path: base.join(&file.path),
+                ..file
+            },
+        })
+        .collect()
+}
+
+/// Read newline-separated genome FASTA paths from reader. Surrounding
+/// whitespace (including Windows line endings) is removed, and blank lines and
+/// lines starting with '#' are skipped. A line may optionally contain a tab
+/// followed by the name of the genome, otherwise the name is derived from the
+/// path with genome_name_from_path(). When any names are given explicitly, an
+/// Err is returned if a name or path appears on more than one line. path is
+/// only used in log and error messages.
+pub fn read_genome_fasta_list<R: BufRead>(
+    reader: R,
+    path: &Path,
+) -> std::result::Result<Vec<GenomeFastaFile>, GenomeSpecificationError> {
+    let mut fasta_files = vec![];
+    let mut line_numbers = vec![];
+    let mut any_named = false;
+    let mut num_skipped = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| GenomeSpecificationError::UnreadableListFile {
+            path: path.to_path_buf(),
+            line: Some(index + 1),
+            source: e,
+        })?;
+        let (fasta_file, named) = match parse_genome_fasta_list_line(&line) {
+            Some(parsed) => parsed,
+            None => {
+                num_skipped += 1;
+                continue;
+            }
+        };
+        any_named |= named;
+        fasta_files.push(fasta_file);
+        line_numbers.push(index + 1);
+    }
+    debug!(
+        "Skipped {} blank or comment line(s) in genome fasta list {}",
+        num_skipped,
+        describe_list_path(path)
+    );
+    if any_named {
+        let mut problems = vec![];
+        let mut name_lines: HashMap<&str, usize> = HashMap::new();
+        let mut path_lines: HashMap<&Path, usize> = HashMap::new();
+        for (fasta_file, line_number) in fasta_files.iter().zip(line_numbers) {
+            if let Some(previous) = name_lines.insert(&fasta_file.name, line_number) {
+                problems.push(format!(
+                    "genome name '{}' on line {} was already used on line {}",
+                    fasta_file.name, line_number, previous
+                ));
+            }
+            if let Some(previous) = path_lines.insert(&fasta_file.path, line_number) {
+                problems.push(format!(
+                    "path '{}' on line {} was already listed on line {}",
+                    fasta_file.path.display(),
+                    line_number,
+                    previous
+                ));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(GenomeSpecificationError::DuplicateListEntries {
+                path: path.to_path_buf(),
+                problems,
+            });
+        }
+    }
+    Ok(fasta_files)
+}
+
+/// Parse a line of a genome FASTA list, returning the genome FASTA file and
+/// whether its name was given explicitly, or None for blank lines and
+/// comments. See read_genome_fasta_list() for the format.
+pub(crate) fn parse_genome_fasta_list_line(line: &str) -> Option<(GenomeFastaFile, bool)> {
+    let line = line.trim();
+    // Skip blank lines and comments
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    Some(match line.split_once('\t') {
+        Some((fasta_path, name)) if !name.trim().is_empty() => (
+            GenomeFastaFile {
+                path: PathBuf::from(fasta_path.trim()),
+                name: name.trim().to_string(),
+            },
+            true,
+        ),
+        Some((fasta_path, _)) => (
+            GenomeFastaFile::from_path(PathBuf::from(fasta_path.trim())),
+            false,
+        ),
+        None => (GenomeFastaFile::from_path(PathBuf::from(line)), false),
+    })
+}
+
+/// Extensions commonly used for nucleotide FASTA files, without the leading dot.
+/// These are removed from file names when deriving genome names.
+pub const KNOWN_FASTA_EXTENSIONS: &[&str] = &["fasta", "fna", "fa", "fas", "fsa", "ffn"];
+
+/// A genome FASTA file along with the name of the genome it contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenomeFastaFile {
+    pub path: PathBuf,
+    pub name: String,
+}
+
+impl AsRef<Path> for GenomeFastaFile {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl GenomeFastaFile {
+    /// Create a GenomeFastaFile named by genome_name_from_path().
+    pub fn from_path(path: PathBuf) -> GenomeFastaFile {
+        let name = genome_name_from_path(&path);
+        GenomeFastaFile { path, name }
+    }
+}
+
+/// Derive a genome name from a genome FASTA path by removing the directory,
+/// any compression suffix (see COMPRESSION_SUFFIXES) and then any FASTA
+/// extension (see KNOWN_FASTA_EXTENSIONS), so that e.g. 'dir/bin.1.fna' and
+/// 'dir/bin.1.fna.gz' are both named 'bin.1'. Other dots in the file name are
+/// kept.
+pub fn genome_name_from_path(path: &Path) -> String {
+    let mut name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    for suffixes in [COMPRESSION_SUFFIXES, KNOWN_FASTA_EXTENSIONS] {
+        let lowercase_name = name.to_ascii_lowercase();
+        if let Some(suffix) = suffixes
+            .iter()
+            .find(|s| file_name_has_extension(&lowercase_name, s))
+        {
+            name.truncate(name.len() - suffix.len() - 1);
+        }
+    }
+    name
+}
+
+/// Check that no two genome FASTA files would be given the same genome name by
+/// genome_name_from_path(). Returns an Err listing every collision, in the
+/// order each name was first seen.
+pub fn validate_genome_fasta_files(
+    paths: &[PathBuf],
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let files: Vec<GenomeFastaFile> = paths
+        .iter()
+        .map(|p| GenomeFastaFile::from_path(p.clone()))
+        .collect();
+    check_genome_names_are_unique(&files)
+}
+
+/// Check that no two genome FASTA paths would be given the same genome name by
+/// genome_name_from_path(), i.e. after removing directories and known
+/// (possibly compressed) FASTA extensions. The Err lists each duplicated name
+/// along with the full paths which share it.
+pub fn check_genome_name_uniqueness(paths: &[String]) -> std::result::Result<(), String> {
+    let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    validate_genome_fasta_files(&paths).map_err(|e| e.to_string())
+}
+
+/// Check that no two GenomeFastaFiles have the same name. Returns an Err
+/// listing every collision, in the order each name was first seen.
+pub fn check_genome_names_are_unique(
+    files: &[GenomeFastaFile],
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let mut names: Vec<(String, Vec<PathBuf>)> = vec![];
+    let mut name_to_index: HashMap<&str, usize> = HashMap::new();
+    for file in files {
+        match name_to_index.get(file.name.as_str()) {
+            Some(i) => names[*i].1.push(file.path.clone()),
+            None => {
+                name_to_index.insert(&file.name, names.len());
+                names.push((file.name.clone(), vec![file.path.clone()]));
+            }
+        }
+    }
+    let duplicates: Vec<_> = names
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect();
+    match duplicates.is_empty() {
+        true => Ok(()),
+        false => Err(GenomeSpecificationError::DuplicateGenomeNames { duplicates }),
+    }
+}
+
+/// Number of paths above which checks of genome FASTA files are spread across
+/// threads, when more than one thread is allowed, unless
+/// GenomeSpecificationOptions::parallel_validation_threshold is set.
+pub const PARALLEL_VALIDATION_THRESHOLD: usize = 1000;
+
+/// Check genome FASTA files as options.validation and options.empty_files
+/// ask, using options.threads threads for large numbers of genomes.
+pub fn check_genome_fasta_files(
+    paths: &[PathBuf],
+    options: &GenomeSpecificationOptions,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let threshold = options
+        .parallel_validation_threshold
+        .unwrap_or(PARALLEL_VALIDATION_THRESHOLD);
+    if options.validation >= ValidationLevel::Exists {
+        genome_fasta_files_exist(paths, options.threads, threshold)?;
+    }
+    if options.empty_files != EmptyFileAction::Ignore {
+        empty_genome_fasta_files(paths, options.empty_files, options.threads, threshold)?;
+    }
+    if options.validation >= ValidationLevel::FastaHeader {
+        fasta_contents(
+            paths,
+            options.validation >= ValidationLevel::NucleotideFasta,
+            options.threads,
+            threshold,
+        )?;
+    }
+    Ok(())
+}
+
+/// Apply check to each path, returning the results in the same order as paths.
+/// When there are more than threshold paths, the work is split across at most
+/// threads threads, which bounds the number of concurrent filesystem
+/// operations.
+fn check_paths<T, F>(paths: &[PathBuf], threads: usize, threshold: usize, check: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&PathBuf) -> T + Sync,
+{
+    if threads <= 1 || paths.len() <= threshold {
+        return paths.iter().map(check).collect();
+    }
+    debug!("Checking {} genome FASTA files using {} threads", paths.len(), threads);
+    let chunk_size = paths.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let check = &check;
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(check).collect::<Vec<T>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Thread checking genome FASTA files panicked"))
+            .collect()
+    })
+}
+
+/// Check that each path exists, is a regular file (or a symlink to one) and can
+/// be opened for reading. All problems are reported together in the returned
+/// Err rather than stopping at the first. Empty files are not checked for here,
+/// see check_for_empty_genome_fasta_files(). See check_paths() for threads.
+pub fn check_genome_fasta_files_exist(
+    paths: &[PathBuf],
+    threads: usize,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    genome_fasta_files_exist(paths, threads, PARALLEL_VALIDATION_THRESHOLD)
+}
+
+fn genome_fasta_files_exist(
+    paths: &[PathBuf],
+    threads: usize,
+    threshold: usize,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let problems = check_paths(paths, threads, threshold, |path| match std::fs::metadata(path) {
+        Err(e) => Some(e.to_string()),
+        Ok(metadata) => {
+            if !metadata.is_file() {
+                Some("not a regular file".to_string())
+            } else if let Err(e) = File::open(path) {
+                Some(format!("unable to open for reading: {}", e))
+            } else {
+                None
+            }
+        }
+    });
+    let unusable: Vec<(PathBuf, String)> = paths
+        .iter()
+        .zip(problems)
+        .filter_map(|(path, problem)| problem.map(|p| (path.clone(), p)))
+        .collect();
+    match unusable.is_empty() {
+        true => Ok(()),
+        false => Err(GenomeSpecificationError::UnusableGenomeFiles { files: unusable }),
+    }
+}
+
+/// Check for genome FASTA files which are zero bytes long, e.g. left behind by
+/// a failed pipeline step. All empty files are listed in a single warning, or
+/// in the returned Err if action is EmptyFileAction::Fail. Nothing is checked
+/// if action is EmptyFileAction::Ignore. Paths which cannot
+/// be stat'd are ignored here, see check_genome_fasta_files_exist(). See
+/// check_paths() for threads.
+pub fn check_for_empty_genome_fasta_files(
+    paths: &[PathBuf],
+    action: EmptyFileAction,
+    threads: usize,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    empty_genome_fasta_files(paths, action, threads, PARALLEL_VALIDATION_THRESHOLD)
+}
+
+fn empty_genome_fasta_files(
+    paths: &[PathBuf],
+    action: EmptyFileAction,
+    threads: usize,
+    threshold: usize,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    if action == EmptyFileAction::Ignore {
+        return Ok(());
+    }
+    let is_empty = check_paths(paths, threads, threshold, |path| {
+        matches!(std::fs::metadata(path), Ok(metadata) if metadata.len() == 0)
+    });
+    let empty: Vec<PathBuf> = paths
+        .iter()
+        .zip(is_empty)
+        .filter(|(_, is_empty)| *is_empty)
+        .map(|(path, _)| path.clone())
+        .collect();
+    if empty.is_empty() {
+        return Ok(());
+    }
+    match action {
+        EmptyFileAction::Warn => {
+            warn!(
+                "{} genome FASTA file(s) are empty: {}",
+                empty.len(),
+                empty
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            Ok(())
+        }
+        EmptyFileAction::Fail => Err(GenomeSpecificationError::EmptyGenomeFiles { files: empty }),
+        EmptyFileAction::Ignore => Ok(()),
+    }
+}
+
+/// Check that each file looks like FASTA: after decompression if it is gzipped,
+/// the first non-whitespace byte must be '>'. If require_nucleotide, the
+/// sequence of the first record must also be mostly nucleotide characters, so
+/// that protein FASTA files are rejected. All problems are reported together.
+/// See check_paths() for threads.
+pub fn validate_fasta_contents(
+    paths: &[PathBuf],
+    require_nucleotide: bool,
+    threads: usize,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    fasta_contents(paths, require_nucleotide, threads, PARALLEL_VALIDATION_THRESHOLD)
+}
+
+fn fasta_contents(
+    paths: &[PathBuf],
+    require_nucleotide: bool,
+    threads: usize,
+    threshold: usize,
+) -> std::result::Result<(), GenomeSpecificationError> {
+    let results = check_paths(paths, threads, threshold, |path| {
+        check_fasta_contents(path, require_nucleotide)
+    });
+    let unusable: Vec<(PathBuf, String)> = paths
+        .iter()
+        .zip(results)
+        .filter_map(|(path, result)| result.err().map(|reason| (path.clone(), reason)))
+        .collect();
+    match unusable.is_empty() {
+        true => Ok(()),
+        false => Err(GenomeSpecificationError::UnusableGenomeFiles { files: unusable }),
+    }
+}
+
+/// Number of bytes read from the start of each file when sniffing its contents.
+const FASTA_SNIFF_LENGTH: u64 = 65536;
+
+/// Minimum fraction of nucleotide characters in the first record for a file to
+/// be considered nucleotide rather than amino acid FASTA.
+const MIN_NUCLEOTIDE_FRACTION: f64 = 0.9;
+
+fn check_fasta_contents(path: &Path, require_nucleotide: bool) -> std::result::Result<(), String> {
+    let mut file = File::open(path).map_err(|e| format!("unable to open for reading: {}", e))?;
+    let mut magic = [0u8; 2];
+    let is_gzip = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    let file = File::open(path).map_err(|e| format!("unable to open for reading: {}", e))?;
+    let reader: Box<dyn Read> = match is_gzip {
+        true => Box::new(MultiGzDecoder::new(file)),
+        false => Box::new(file),
+    };
+    let mut start = vec![];
+    reader
+        .take(FASTA_SNIFF_LENGTH)
+        .read_to_end(&mut start)
+        .map_err(|e| match is_gzip {
+            true => format!("appears to be gzip compressed but could not be decompressed: {}", e),
+            false => format!("error while reading: {}", e),
+        })?;
+
+    match start.iter().find(|b| !b.is_ascii_whitespace()) {
+        None => return Err("file is empty or contains only whitespace".to_string()),
+        Some(b'>') => {}
+        Some(_) => return Err("does not start with a FASTA header ('>')".to_string()),
+    }
+
+    if require_nucleotide {
+        // The sequence of the first record runs from the end of the first
+        // header line to the start of the second header, if any.
+        let first_line_end = start.iter().position(|b| *b == b'\n').unwrap_or(start.len());
+        let sequence = &start[first_line_end..];
+        let sequence = match sequence.iter().position(|b| *b == b'>') {
+            Some(i) => &sequence[..i],
+            None => sequence,
+        };
+        let mut num_residues = 0;
+        let mut num_nucleotides = 0;
+        for b in sequence.iter().filter(|b| !b.is_ascii_whitespace()) {
+            num_residues += 1;
+            if matches!(b.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'U' | b'N' | b'-') {
+                num_nucleotides += 1;
+            }
+        }
+        if num_residues == 0 {
+            return Err("first FASTA record has no sequence".to_string());
+        }
+        if (num_nucleotides as f64) < MIN_NUCLEOTIDE_FRACTION * num_residues as f64 {
+            return Err("appears to contain amino acid rather than nucleotide sequence".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Replace the path of each genome FASTA file with its canonical form (see
+/// std::fs::canonicalize()), then remove duplicates as
+/// deduplicate_genome_fasta_files() does. Returns an Err listing each file
+/// which could not be canonicalized, e.g. as it is missing.
+pub fn canonicalize_genome_fasta_files(
+    files: Vec<GenomeFastaFile>,
+) -> std::result::Result<Vec<GenomeFastaFile>, GenomeSpecificationError> {
+    let mut canonical_files = Vec::with_capacity(files.len());
+    let mut unusable = vec![];
+    for file in files {
+        match std::fs::canonicalize(&file.path) {
+            Ok(path) => canonical_files.push(GenomeFastaFile { path, ..file }),
+            Err(e) => unusable.push((file.path, e.to_string())),
+        }
+    }
+    if !unusable.is_empty() {
+        return Err(GenomeSpecificationError::UnusableGenomeFiles { files: unusable });
+    }
+    Ok(deduplicate_genome_fasta_files(canonical_files))
+}
+
+/// Remove genome FASTA files whose path is the same as that of an earlier
+/// one, keeping the first of each. Paths are compared as given, so e.g.
+/// 'bins/a.fna' and 'bins/../bins/a.fna' are not considered the same - use
+/// canonicalize_genome_fasta_files() for that. Repeated paths are listed in a
+/// warning.
+pub fn deduplicate_genome_fasta_files(files: Vec<GenomeFastaFile>) -> Vec<GenomeFastaFile> {
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    let mut repeated = vec![];
+    let mut deduplicated = Vec::with_capacity(files.len());
+    for file in files {
+        let count = counts.entry(file.path.clone()).or_insert(0);
+        *count += 1;
+        match *count {
+            1 => deduplicated.push(file),
+            2 => repeated.push(file.path),
+            _ => {}
+        }
+    }
+    if !repeated.is_empty() {
+        warn!(
+            "Removed repeated genome FASTA file(s) so each is only used once: {}",
+            repeated
+                .iter()
+                .map(|p| format!("'{}' ({} times)", p.display(), counts[p]))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    deduplicated
+}
+
+/// Describe the number and total size of genome FASTA files, along with the
+/// smallest and largest of them, e.g. "Found 1,234 genome FASTA files
+/// totalling 5.2 GiB (smallest 'a.fna' 1.1 MiB, largest 'b.fna' 9.3 MiB)".
+/// Files which cannot be read are counted as zero bytes, with a warning.
+pub fn summarize_genome_fasta_files<P: AsRef<Path>>(paths: &[P]) -> String {
+    let mut total: u64 = 0;
+    let mut smallest: Option<(&Path, u64)> = None;
+    let mut largest: Option<(&Path, u64)> = None;
+    let mut unreadable = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        let size = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                unreadable.push(format!("{} ({})", path.display(), e));
+                0
+            }
+        };
+        total += size;
+        if smallest.map(|(_, s)| size < s).unwrap_or(true) {
+            smallest = Some((path, size));
+        }
+        if largest.map(|(_, s)| size > s).unwrap_or(true) {
+            largest = Some((path, size));
+        }
+    }
+    if !unreadable.is_empty() {
+        warn!(
+            "Counting {} genome FASTA file(s) which could not be read as empty: {}",
+            unreadable.len(),
+            unreadable.join(", ")
+        );
+    }
+    let mut summary = format!(
+        "Found {} genome FASTA file{} totalling {}",
+        format_count(paths.len()),
+        match paths.len() {
+            1 => "",
+            _ => "s",
+        },
+        format_size(total)
+    );
+    if let (Some((smallest, smallest_size)), Some((largest, largest_size))) = (smallest, largest) {
+        if paths.len() > 1 {
+            summary.push_str(&format!(
+                " (smallest '{}' {}, largest '{}' {})",
+                smallest.display(),
+                format_size(smallest_size),
+                largest.display(),
+                format_size(largest_size)
+            ));
+        }
+    }
+    summary
+}
+
+/// Format a count with commas separating thousands, e.g. "1,234".
+pub(crate) fn format_count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+/// Format a number of bytes using binary units, e.g. "5.2 GiB".
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Remove paths whose file name matches any of patterns. A pattern containing
+/// glob metacharacters ('*', '?' or '[') is matched as a glob against the whole
+/// file name, otherwise a file name matches if it contains the pattern.
+pub fn exclude_genome_fasta_files<P: AsRef<Path>>(
+    paths: Vec<P>,
+    patterns: &[String],
+) -> std::result::Result<Vec<P>, GenomeSpecificationError> {
+    let exclusions = ExclusionPatterns::new(patterns)?;
+    Ok(paths
+        .into_iter()
+        .filter(|path| !exclusions.excludes(path.as_ref()))
+        .collect())
+}
+
+/// Patterns given to exclude genome FASTA files, parsed ready for matching. See
+/// exclude_genome_fasta_files().
+pub(crate) struct ExclusionPatterns {
+    patterns: Vec<(String, Option<glob::Pattern>)>,
+}
+
+impl ExclusionPatterns {
+    pub(crate) fn new(
+        patterns: &[String],
+    ) -> std::result::Result<ExclusionPatterns, GenomeSpecificationError> {
+        let mut parsed = vec![];
+        for pattern in patterns {
+            if pattern.contains(['*', '?', '[']) {
+                let glob = glob::Pattern::new(pattern).map_err(|e| {
+                    GenomeSpecificationError::InvalidGlobPattern {
+                        pattern: pattern.clone(),
+                        message: e.to_string(),
+                    }
+                })?;
+                parsed.push((pattern.clone(), Some(glob)));
+            } else {
+                parsed.push((pattern.clone(), None));
+            }
+        }
+        Ok(ExclusionPatterns { patterns: parsed })
+    }
+
+    /// Whether path is excluded by any of the patterns, logging which if so.
+    pub(crate) fn excludes(&self, path: &Path) -> bool {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let excluded_by = self.patterns.iter().find(|(pattern, glob)| match glob {
+            Some(glob) => glob.matches(&file_name),
+            None => file_name.contains(pattern.as_str()),
+        });
+        match excluded_by {
+            Some((pattern, _)) => {
+                crate::info_limited!(
+                    SKIPPED_GENOME_MESSAGE_KEY,
+                    SKIPPED_GENOME_MESSAGE_LIMIT,
+                    "Not using genome FASTA file '{}' as it matches the exclusion pattern '{}'",
+                    path.display(),
+                    pattern
+                );
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Genome FASTA files found from clap arguments, along with any temporary
+/// directories genomes were extracted into. The extracted genome FASTA files
+/// are deleted when this is dropped, so it must be kept alive for as long as
+/// they are in use.
+#[derive(Debug)]
+pub struct ParsedGenomeFastaFiles {
+    pub files: Vec<GenomeFastaFile>,
+    pub temporary_directories: Vec<tempfile::TempDir>,
+}
+
+/// Extract the members of a tar archive (optionally gzip compressed) for which
+/// is_genome_fasta returns true into a new temporary directory, returning the
+/// directory along with the paths of the extracted files, sorted. Only regular
+/// files are extracted, and members whose paths would escape the temporary
+/// directory are skipped. The files are deleted when the returned TempDir is
+/// dropped, so it must be kept alive for as long as they are in use.
+pub fn extract_genome_fasta_archive<F: Fn(&Path) -> bool>(
+    archive: &Path,
+    is_genome_fasta: F,
+) -> std::result::Result<(tempfile::TempDir, Vec<PathBuf>), GenomeSpecificationError> {
+    let unreadable = |source| GenomeSpecificationError::UnreadableArchive {
+        archive: archive.to_path_buf(),
+        source,
+    };
+    let mut reader = BufReader::new(File::open(archive).map_err(unreadable)?);
+    // Detect gzip from the magic bytes rather than the file name, so that
+    // e.g. '.tgz' archives and misnamed ones work.
+    let is_gzip = reader.fill_buf().map_err(unreadable)?.starts_with(&GZIP_MAGIC);
+    let reader: Box<dyn Read> = match is_gzip {
+        true => Box::new(MultiGzDecoder::new(reader)),
+        false => Box::new(reader),
+    };
+    let temporary_directory = tempfile::Builder::new()
+        .prefix("bird_tool_utils_genome_archive")
+        .tempdir()
+        .map_err(unreadable)?;
+    let mut genome_fasta_files = vec![];
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries().map_err(unreadable)? {
+        let mut entry = entry.map_err(unreadable)?;
+        let member = entry.path().map_err(unreadable)?.into_owned();
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            debug!("Not extracting non-file archive member '{}'", member.display());
+        } else if is_genome_fasta(&member) {
+            match entry.unpack_in(temporary_directory.path()).map_err(unreadable)? {
+                true => genome_fasta_files.push(temporary_directory.path().join(&member)),
+                false => warn!(
+                    "Not extracting archive member '{}' from '{}' as its path is outside the archive",
+                    member.display(),
+                    archive.display()
+                ),
+            }
+        }
+    }
+    genome_fasta_files.sort();
+    genome_fasta_files.dedup();
+    debug!(
+        "Extracted {} genome FASTA file(s) from '{}' into '{}'",
+        genome_fasta_files.len(),
+        archive.display(),
+        temporary_directory.path().display()
+    );
+    Ok((temporary_directory, genome_fasta_files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_has_extension() {
+        assert!(file_name_has_extension("foo.fna.gz", "fna.gz"));
+        assert!(file_name_has_extension("foo.fna", "fna"));
+        assert!(!file_name_has_extension("foo.fna.gz", "fna"));
+        assert!(!file_name_has_extension("foo.gz", "fna"));
+        assert!(!file_name_has_extension("foo.fna.bak", "fna"));
+        assert!(!file_name_has_extension("foofna", "fna"));
+        assert!(!file_name_has_extension("fna", "fna"));
+        assert!(!file_name_has_extension(".fna", "fna"));
+    }
+
+    #[test]
+    fn test_read_genome_fasta_list_file_comments_blank_lines_and_crlf() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("genomes.txt");
+        std::fs::write(
+            &list,
+            "# genomes\r\na.fna\r\n\r\n   \r\n  # indented comment\r\nb.fna\r\nc.fna",
+        )
+        .unwrap();
+        let paths: Vec<PathBuf> = read_genome_fasta_list_file(&list)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(
+            vec![PathBuf::from("a.fna"), PathBuf::from("b.fna"), PathBuf::from("c.fna")],
+            paths
+        );
+    }
+
+    #[test]
+    fn test_check_genome_name_uniqueness_compressed_collision() {
+        let paths = vec![
+            "bins/a.fna".to_string(),
+            "b.fna".to_string(),
+            "other/a.fna.gz".to_string(),
+            "c.fa.gz".to_string(),
+        ];
+        let e = check_genome_name_uniqueness(&paths).unwrap_err();
+        assert!(e.contains("'a'"), "{}", e);
+        assert!(e.contains("bins/a.fna"), "{}", e);
+        assert!(e.contains("other/a.fna.gz"), "{}", e);
+        assert!(!e.contains("b.fna") && !e.contains("c.fa.gz"), "{}", e);
+
+        assert!(check_genome_name_uniqueness(&paths[1..]).is_ok());
+    }
+
+    #[test]
+    fn test_check_genome_fasta_files_exist_reports_all_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("good.fna"), ">contig\nACGT\n").unwrap();
+        std::fs::write(dir.path().join("empty.fna"), "").unwrap();
+        let list = dir.path().join("genomes.txt");
+        std::fs::write(&list, "good.fna\nmissing1.fna\nempty.fna\nmissing2.fna\n").unwrap();
+        let paths: Vec<PathBuf> = resolve_genome_fasta_list_paths(
+            read_genome_fasta_list_file(&list).unwrap(),
+            &list,
+        )
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+
+        match check_genome_fasta_files_exist(&paths, 1) {
+            Err(GenomeSpecificationError::UnusableGenomeFiles { files }) => assert_eq!(
+                vec![dir.path().join("missing1.fna"), dir.path().join("missing2.fna")],
+                files.into_iter().map(|(path, _)| path).collect::<Vec<_>>()
+            ),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        // The empty file is reported separately
+        match check_for_empty_genome_fasta_files(&paths, EmptyFileAction::Fail, 1) {
+            Err(GenomeSpecificationError::EmptyGenomeFiles { files }) => {
+                assert_eq!(vec![dir.path().join("empty.fna")], files)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_check_genome_fasta_files_empty_and_whitespace_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.fna");
+        let empty = dir.path().join("empty.fna");
+        let whitespace = dir.path().join("whitespace.fna");
+        std::fs::write(&good, ">contig\nACGT\n").unwrap();
+        std::fs::write(&empty, "").unwrap();
+        std::fs::write(&whitespace, " \n\t\n").unwrap();
+        let paths = vec![good, empty.clone(), whitespace.clone()];
+
+        // Empty files are found without any validation being asked for
+        let fail = GenomeSpecificationOptions {
+            empty_files: EmptyFileAction::Fail,
+            ..Default::default()
+        };
+        match check_genome_fasta_files(&paths, &fail) {
+            Err(GenomeSpecificationError::EmptyGenomeFiles { files }) => {
+                assert_eq!(vec![empty.clone()], files)
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert!(check_genome_fasta_files(&paths, &Default::default()).is_ok());
+        let ignore = GenomeSpecificationOptions {
+            empty_files: EmptyFileAction::Ignore,
+            ..fail.clone()
+        };
+        assert!(check_genome_fasta_files(&paths, &ignore).is_ok());
+
+        // Files containing only whitespace are caught by checking the header
+        let fasta_header = GenomeSpecificationOptions {
+            validation: ValidationLevel::FastaHeader,
+            ..Default::default()
+        };
+        match check_genome_fasta_files(&paths, &fasta_header) {
+            Err(GenomeSpecificationError::UnusableGenomeFiles { files }) => assert_eq!(
+                vec![empty, whitespace],
+                files.into_iter().map(|(path, _)| path).collect::<Vec<_>>()
+            ),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_gzipped_genome_fasta_list_matches_plain() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let contents = "# genomes\na.fna\nbins/b.fna.gz\tgenome_b\n\nc.fa\n";
+        let plain = dir.path().join("genomes.txt");
+        std::fs::write(&plain, contents).unwrap();
+        // Detected by the magic bytes rather than the file name
+        for name in ["genomes.txt.gz", "genomes_gzipped.txt"] {
+            let gzipped = dir.path().join(name);
+            let mut encoder = flate2::write::GzEncoder::new(
+                File::create(&gzipped).unwrap(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(contents.as_bytes()).unwrap();
+            encoder.finish().unwrap();
+
+            assert_eq!(
+                read_genome_fasta_list_file(&plain).unwrap(),
+                read_genome_fasta_list_file(&gzipped).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_paths_uses_threads_above_threshold() {
+        let paths: Vec<PathBuf> = (0..8).map(|i| PathBuf::from(format!("{}.fna", i))).collect();
+        let thread_ids = check_paths(&paths, 4, 1, |_| std::thread::current().id());
+        assert_eq!(paths.len(), thread_ids.len());
+        let distinct: std::collections::HashSet<_> = thread_ids.into_iter().collect();
+        assert!(distinct.len() > 1);
+
+        let thread_ids = check_paths(&paths, 4, paths.len(), |_| std::thread::current().id());
+        assert!(thread_ids.iter().all(|id| *id == std::thread::current().id()));
+    }
+
+    #[test]
+    fn test_check_genome_fasta_files_parallel_matches_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = vec![];
+        for i in 0..5 {
+            let path = dir.path().join(format!("good{}.fna", i));
+            std::fs::write(&path, ">contig\nACGTACGTAC\n").unwrap();
+            paths.push(path);
+        }
+        let contents = [
+            ("empty.fna", ""),
+            ("protein.fna", ">protein\nMKLVWWQERTYPLKHHGF\n"),
+            ("not_fasta.fna", "ACGT\n"),
+        ];
+        for (name, content) in contents {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            paths.insert(2, path);
+        }
+        let with_missing: Vec<PathBuf> = paths
+            .iter()
+            .cloned()
+            .chain(std::iter::once(dir.path().join("missing.fna")))
+            .collect();
+        // The missing file is reported before the contents of files are read
+        for (paths, num_unusable) in [(paths, 3), (with_missing, 1)] {
+            let sequential = GenomeSpecificationOptions {
+                validation: ValidationLevel::NucleotideFasta,
+                ..Default::default()
+            };
+            let parallel = GenomeSpecificationOptions {
+                threads: 4,
+                parallel_validation_threshold: Some(1),
+                ..sequential.clone()
+            };
+            let sequential = check_genome_fasta_files(&paths, &sequential).unwrap_err();
+            let parallel = check_genome_fasta_files(&paths, &parallel).unwrap_err();
+            assert_eq!(sequential.to_string(), parallel.to_string());
+            match parallel {
+                GenomeSpecificationError::UnusableGenomeFiles { files } => {
+                    assert_eq!(num_unusable, files.len())
+                }
+                e => panic!("Unexpected error {:?}", e),
+            }
+        }
+    }
+}
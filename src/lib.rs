@@ -1,6 +1,21 @@
 pub mod clap_utils;
+#[allow(clippy::expect_fun_call, clippy::needless_return)]
 pub mod command;
+#[allow(clippy::expect_fun_call, clippy::needless_return)]
 pub mod external_command_checker;
+pub mod genome_directory;
+pub mod genome_download;
+pub mod genome_iterator;
+pub mod genome_manifest;
+pub mod genome_specification;
+pub mod logging;
+#[cfg(feature = "progress")]
+pub mod progress;
 
 #[macro_use]
 extern crate log;
+
+/// Held by tests while they change environment variables, which are shared
+/// by all tests running in parallel.
+#[cfg(test)]
+pub(crate) static TEST_ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
@@ -0,0 +1,1205 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::Cell;
+use std::env;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use env_logger::{Builder, WriteStyle};
+use log::LevelFilter;
+
+/// Set up logging at level, for use where there are no clap arguments, e.g.
+/// in a library or daemon. Tools with command line arguments usually use
+/// clap_utils::set_log_level() instead, which calls this with the level set
+/// by --verbose and --quiet. Filters in RUST_LOG (or
+/// LoggingOptions::filter_env_var) override level. If is_last, the program
+/// name and version are logged once logging is set up, along with how it was
+/// run if LoggingOptions::log_invocation (see log_program_invocation()); for
+/// tools with subcommands it should be false when setting up logging for the
+/// top level, and true for the subcommand. Warnings and errors which are logged are counted, see
+/// log_warning_summary().
+///
+/// If a logger has already been installed,
+/// SetLogLevelError::AlreadyInitialised is returned, which callers can
+/// usually ignore, unless in test mode (see LoggingOptions::test_mode).
+pub fn init_logging(
+    level: LevelFilter,
+    program_name: &str,
+    version: &str,
+    options: &LoggingOptions,
+    is_last: bool,
+) -> std::result::Result<(), SetLogLevelError> {
+    let env_filters = options
+        .filter_env_var
+        .iter()
+        .map(|v| v.as_str())
+        .chain(["RUST_LOG"])
+        .find_map(|variable| env::var(variable).ok().map(|filters| (variable, filters)));
+    let (mut builder, filter_problems) = logger_builder(
+        level,
+        program_name,
+        version,
+        options,
+        env_filters.as_ref().map(|(_, filters)| filters.as_str()),
+    );
+    let log_file = match &options.log_file {
+        Some(path) => Some(open_log_file(path, options.log_file_append).map_err(|e| {
+            SetLogLevelError::UnopenableLogFile {
+                path: path.clone(),
+                source: e,
+            }
+        })?),
+        None => None,
+    };
+    builder.target(env_logger_target(&options.target, log_file));
+    let (write_style, reason) =
+        log_write_style(options.color, &options.target, options.log_file.is_some());
+    builder.write_style(write_style);
+    let test_mode = options.test_mode || env::var_os(TEST_LOGGING_ENV_VAR).is_some();
+    if test_mode {
+        builder.is_test(true);
+    }
+    let logger = builder.build();
+    let max_level = logger.filter();
+    RETAINED_WARNING_LIMIT.store(options.retained_warnings, Ordering::Relaxed);
+    match log::set_boxed_logger(Box::new(WarningCountingLogger { inner: logger })) {
+        Ok(()) => log::set_max_level(max_level),
+        Err(_) if test_mode => return Ok(()),
+        Err(e) => return Err(SetLogLevelError::AlreadyInitialised(e)),
+    }
+    debug!(
+        "{} log output, as {}",
+        match write_style {
+            WriteStyle::Never => "Not colouring",
+            _ => "Colouring",
+        },
+        reason
+    );
+    if !options.module_filters.is_empty() {
+        debug!(
+            "Applied log filters {}",
+            options
+                .module_filters
+                .iter()
+                .map(|(module, level)| format!("{}={}", module, level))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+    }
+    if let Some((variable, filters)) = &env_filters {
+        for problem in &filter_problems {
+            warn!("Ignoring part of {}='{}': {}", variable, filters, problem);
+        }
+        debug!(
+            "Using log filters '{}' from {}, which override the log level {}",
+            filters, variable, level
+        );
+    }
+    if options.log_panics {
+        install_panic_logging_hook(program_name, version);
+    }
+    if is_last {
+        match options.log_invocation {
+            true => log_program_invocation(program_name, version),
+            false => info!("{} version {}", program_name, version),
+        }
+    }
+    Ok(())
+}
+
+/// Make the env_logger Builder used by init_logging(), which filters and
+/// formats records as set by level and options, before the target it writes
+/// to is set. env_filters, e.g. the value of RUST_LOG, are applied after
+/// the level and module filters. Any parts of them which cannot be parsed are
+/// left out, and the problems with them returned.
+fn logger_builder(
+    level: LevelFilter,
+    program_name: &str,
+    version: &str,
+    options: &LoggingOptions,
+    env_filters: Option<&str>,
+) -> (Builder, Vec<String>) {
+    let mut builder = Builder::new();
+    builder.filter_level(level);
+    for (module, level) in &options.module_filters {
+        builder.filter_module(module, *level);
+    }
+    builder.format_timestamp(options.timestamps.precision());
+    let format = RecordFormat {
+        timestamps: options.timestamps,
+        show_thread: options.show_thread,
+        start: match options.elapsed {
+            true => Some(Instant::now()),
+            false => None,
+        },
+    };
+    if options.format == LogFormat::Json {
+        let program_name = program_name.to_string();
+        let version = version.to_string();
+        builder.format(move |buf, record| {
+            write_json_log_record(buf, record, format, &program_name, &version)
+        });
+    } else if format.show_thread || format.start.is_some() {
+        // Otherwise env_logger's own format is used.
+        builder.format(move |buf, record| write_text_log_record(buf, record, format));
+    }
+    let mut filter_problems = vec![];
+    if let Some(filters) = env_filters {
+        let (valid_filters, problems) = validate_log_filters(filters);
+        builder.parse_filters(&valid_filters);
+        filter_problems = problems;
+    }
+    (builder, filter_problems)
+}
+
+/// Log panics as errors, with the panic message, thread and location, and a
+/// backtrace if RUST_BACKTRACE is set, so that they reach --log-file and any
+/// other log target rather than only stderr. The previously installed panic
+/// hook is called afterwards, so the usual message is still printed to
+/// stderr. This is done by init_logging() if LoggingOptions::log_panics.
+pub fn install_panic_logging_hook(program_name: &str, version: &str) {
+    thread_local! {
+        static LOGGING_PANIC: Cell<bool> = const { Cell::new(false) };
+    }
+    let program_name = program_name.to_string();
+    let version = version.to_string();
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // If logging itself panics, leave it to the previous hook.
+        if !LOGGING_PANIC.with(|logging| logging.replace(true)) {
+            let payload = info.payload();
+            let message = match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(message) => message.clone(),
+                    None => "Box<dyn Any>".to_string(),
+                },
+            };
+            let location = match info.location() {
+                Some(location) => {
+                    format!("{}:{}:{}", location.file(), location.line(), location.column())
+                }
+                None => "an unknown location".to_string(),
+            };
+            error!(
+                "{} version {} panicked in thread '{}' at {}: {}",
+                program_name,
+                version,
+                current_thread_name(),
+                location,
+                message
+            );
+            let backtrace = Backtrace::capture();
+            if backtrace.status() == BacktraceStatus::Captured {
+                error!("Backtrace:\n{}", backtrace);
+            }
+            log::logger().flush();
+            LOGGING_PANIC.with(|logging| logging.set(false));
+        }
+        previous_hook(info);
+    }));
+}
+
+/// Check log filters in the format of RUST_LOG, e.g. 'info,hyper=warn',
+/// returning those which are valid, and descriptions of the problems with
+/// the rest. Module names which look like misspelt log levels, e.g. 'debgu',
+/// are also reported, as they are otherwise silently treated as modules.
+fn validate_log_filters(filters: &str) -> (String, Vec<String>) {
+    let mut problems = vec![];
+    let mut parts = filters.splitn(2, '/');
+    let directives = parts.next().unwrap_or("");
+    let regex = parts.next();
+    if regex.is_some_and(|r| r.contains('/')) {
+        problems.push("only one '/' is allowed".to_string());
+        return ("".to_string(), problems);
+    }
+    let mut valid = vec![];
+    for directive in directives.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+        let fields: Vec<&str> = directive.split('=').map(|f| f.trim()).collect();
+        match fields.as_slice() {
+            [name] if name.parse::<LevelFilter>().is_err() => {
+                let lowercase = name.to_ascii_lowercase();
+                match LevelFilter::iter()
+                    .find(|l| edit_distance(&lowercase, &l.as_str().to_ascii_lowercase()) == 1)
+                {
+                    Some(level) => problems.push(format!(
+                        "'{}' would be treated as a module name, but looks like the log level \
+                         '{}'",
+                        name,
+                        level.as_str().to_ascii_lowercase()
+                    )),
+                    None => valid.push(directive),
+                }
+            }
+            [_] | [_, ""] => valid.push(directive),
+            [_, level] if level.parse::<LevelFilter>().is_ok() => valid.push(directive),
+            [_, level] => problems.push(format!("'{}' is not a log level", level)),
+            _ => problems.push(format!("'{}' has more than one '='", directive)),
+        }
+    }
+    let mut valid = valid.join(",");
+    if let Some(regex) = regex {
+        valid.push('/');
+        valid.push_str(regex);
+    }
+    (valid, problems)
+}
+
+/// Number of single character insertions, deletions, substitutions or swaps
+/// of adjacent characters needed to turn a into b.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i characters of a
+    // and the first j of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let mut distance = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// Log the program name and version, along with how it was run: the full
+/// command line, quoted so that it can be copied and pasted into a shell to
+/// run it again, the working directory, the host and the time. This is done
+/// by init_logging() if LoggingOptions::log_invocation is true.
+pub fn log_program_invocation(program_name: &str, version: &str) {
+    info!("{} version {}", program_name, version);
+    let command_line: Vec<String> = env::args_os()
+        .map(|arg| shell_quote(&arg.to_string_lossy()))
+        .collect();
+    info!("Command line: {}", command_line.join(" "));
+    let working_directory = match env::current_dir() {
+        Ok(dir) => shell_quote(&dir.to_string_lossy()),
+        Err(_) => "unknown".to_string(),
+    };
+    info!(
+        "Working directory {} on host {}, started at {:.0}",
+        working_directory,
+        hostname().unwrap_or_else(|| "unknown".to_string()),
+        jiff::Timestamp::now()
+    );
+}
+
+/// Quote s for a POSIX shell, if needed.
+fn shell_quote(s: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-+=:,./@%".contains(c);
+    match !s.is_empty() && s.chars().all(is_safe) {
+        true => s.to_string(),
+        false => format!("'{}'", s.replace('\'', "'\\''")),
+    }
+}
+
+/// Name of the host, if it can be found.
+fn hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .chain(env::var("HOSTNAME").ok())
+        .chain(env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+}
+
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+static ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+static RETAINED_WARNING_LIMIT: AtomicUsize = AtomicUsize::new(0);
+static RETAINED_WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Logger which counts the warnings and errors logged by the env_logger it
+/// wraps, so that they can be summarised with log_warning_summary().
+struct WarningCountingLogger {
+    inner: env_logger::Logger,
+}
+
+impl log::Log for WarningCountingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= log::Level::Warn && self.inner.matches(record) {
+            let count = match record.level() {
+                log::Level::Error => &ERROR_COUNT,
+                _ => &WARNING_COUNT,
+            };
+            count.fetch_add(1, Ordering::Relaxed);
+            // Only take the lock while there may be room for more.
+            let limit = RETAINED_WARNING_LIMIT.load(Ordering::Relaxed);
+            if warning_count() + error_count() <= limit {
+                if let Ok(mut retained) = RETAINED_WARNINGS.lock() {
+                    if retained.len() < limit {
+                        retained.push(record.args().to_string());
+                    }
+                }
+            }
+        }
+        self.inner.log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Number of warnings logged since logging was set up by init_logging().
+pub fn warning_count() -> usize {
+    WARNING_COUNT.load(Ordering::Relaxed)
+}
+
+/// Number of errors logged since logging was set up by init_logging().
+pub fn error_count() -> usize {
+    ERROR_COUNT.load(Ordering::Relaxed)
+}
+
+/// The first warnings and errors logged, up to
+/// LoggingOptions::retained_warnings of them.
+pub fn retained_warnings() -> Vec<String> {
+    match RETAINED_WARNINGS.lock() {
+        Ok(retained) => retained.clone(),
+        Err(_) => vec![],
+    }
+}
+
+/// Log how many warnings and errors there were, along with the first of
+/// them, e.g. 'Run completed with 17 warnings; first was: ...', so that
+/// users notice warnings logged long before a tool finishes. Tools call this
+/// just before exiting. Nothing is logged if there were none. Tools which
+/// want to exit with a non-zero status after warnings can check
+/// warning_count() and error_count() instead or as well. Messages suppressed
+/// by warn_limited!() and info_limited!() are also summarised.
+pub fn log_warning_summary() {
+    log_suppressed_messages();
+    let warnings = warning_count();
+    let errors = error_count();
+    if warnings == 0 && errors == 0 {
+        return;
+    }
+    let plural = |count: usize, noun: &str| match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    };
+    let counts = match (warnings, errors) {
+        (_, 0) => plural(warnings, "warning"),
+        (0, _) => plural(errors, "error"),
+        (_, _) => format!("{} and {}", plural(warnings, "warning"), plural(errors, "error")),
+    };
+    match retained_warnings().first() {
+        Some(first) => info!("Run completed with {}; first was: {}", counts, first),
+        None => info!("Run completed with {}", counts),
+    }
+}
+
+/// Log a message at most limit times for each key, e.g.
+/// log_limited!(log::Level::Info, "skipped file", 100, "Not using '{}'", path)
+/// so that a message logged for each of many thousands of files does not make
+/// the log enormous. When the limit is first reached, a message saying that
+/// further messages are suppressed is logged, and log_suppressed_messages()
+/// logs how many were. The key must be a &'static str, and the calling crate
+/// must depend on the log crate.
+#[macro_export]
+macro_rules! log_limited {
+    ($level:expr, $key:expr, $limit:expr, $($arg:tt)+) => {
+        if ::log::log_enabled!($level)
+            && $crate::logging::limited_message_allowed($key, $level, $limit)
+        {
+            ::log::log!($level, $($arg)+);
+        }
+    };
+}
+
+/// As log_limited!() at the warn level.
+#[macro_export]
+macro_rules! warn_limited {
+    ($key:expr, $limit:expr, $($arg:tt)+) => {
+        $crate::log_limited!(::log::Level::Warn, $key, $limit, $($arg)+)
+    };
+}
+
+/// As log_limited!() at the info level.
+#[macro_export]
+macro_rules! info_limited {
+    ($key:expr, $limit:expr, $($arg:tt)+) => {
+        $crate::log_limited!(::log::Level::Info, $key, $limit, $($arg)+)
+    };
+}
+
+/// How many times messages with a key have been logged with log_limited!().
+struct LimitedMessages {
+    key: &'static str,
+    level: log::Level,
+    limit: usize,
+    count: usize,
+}
+
+static LIMITED_MESSAGES: Mutex<Vec<LimitedMessages>> = Mutex::new(Vec::new());
+
+/// Count a message with key, returning whether it should be logged, for
+/// log_limited!().
+#[doc(hidden)]
+pub fn limited_message_allowed(key: &'static str, level: log::Level, limit: usize) -> bool {
+    let count = {
+        let mut limited = match LIMITED_MESSAGES.lock() {
+            Ok(limited) => limited,
+            Err(_) => return true,
+        };
+        // There are only ever a few keys, so a linear search is fine.
+        match limited.iter_mut().find(|m| m.key == key) {
+            Some(messages) => {
+                messages.count += 1;
+                messages.count
+            }
+            None => {
+                limited.push(LimitedMessages {
+                    key,
+                    level,
+                    limit,
+                    count: 1,
+                });
+                1
+            }
+        }
+    };
+    if count == limit + 1 {
+        log!(
+            level,
+            "Not logging any more '{}' messages after the first {}",
+            key,
+            limit
+        );
+    }
+    count <= limit
+}
+
+/// Log how many messages were suppressed by log_limited!() for each key,
+/// e.g. '... and 39900 more similar 'skipped file' messages were suppressed'.
+pub fn log_suppressed_messages() {
+    let limited = match LIMITED_MESSAGES.lock() {
+        Ok(limited) => limited
+            .iter()
+            .filter(|m| m.count > m.limit)
+            .map(|m| (m.key, m.level, m.count - m.limit))
+            .collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    for (key, level, suppressed) in limited {
+        log!(
+            level,
+            "... and {} more similar '{}' messages were suppressed",
+            suppressed,
+            key
+        );
+    }
+}
+
+/// Set up logging at level unless a logger has already been installed, in
+/// which case nothing is done. Unlike clap_utils::set_log_level(), this can
+/// be called any number of times, so code which just needs logging to work,
+/// e.g. library functions, examples or tests, can call it rather than
+/// working out which call to set_log_level() should have is_last set, as
+/// tools with several binaries or subcommands must. The program name and
+/// version are not logged.
+pub fn ensure_logging(level: LevelFilter) {
+    let _ = init_logging(level, "", "", &LoggingOptions::default(), false);
+}
+
+/// Options controlling the format of log output, for use with init_logging()
+/// and clap_utils::set_log_level_with_options(). Where the corresponding
+/// flags added by clap_utils::add_clap_logging_flags() are given, they take
+/// precedence.
+#[derive(Debug, Clone)]
+pub struct LoggingOptions {
+    /// How each log line is timestamped.
+    pub timestamps: LogTimestamps,
+    /// Whether log output is coloured, as set by --color and --no-color.
+    pub color: LogColor,
+    /// Where log output is written.
+    pub target: LogTarget,
+    /// File log output is written to as well as the target, as set by
+    /// --log-file.
+    pub log_file: Option<PathBuf>,
+    /// Append to log_file rather than overwriting it, as set by
+    /// --log-file-append.
+    pub log_file_append: bool,
+    /// Format of each log line, as set by --log-format.
+    pub format: LogFormat,
+    /// Levels for log records whose target starts with the given module
+    /// prefix, e.g. ("hyper", LevelFilter::Warn) to quieten a noisy
+    /// dependency. These override the level set by --verbose, --quiet and
+    /// --log-level, but are themselves overridden for modules named in
+    /// RUST_LOG.
+    pub module_filters: Vec<(String, log::LevelFilter)>,
+    /// Environment variable log filters are read from in preference to
+    /// RUST_LOG, e.g. 'COVERM_LOG', so that a tool's logging can be set
+    /// without affecting other programs. Filters which cannot be parsed are
+    /// warned about and ignored.
+    pub filter_env_var: Option<String>,
+    /// Environment variable the log level is read from by
+    /// clap_utils::set_log_level() when none of --verbose, --quiet or
+    /// --log-level is given, or None to not read one. Defaults to
+    /// DEFAULT_LOG_LEVEL_ENV_VAR.
+    pub log_level_env_var: Option<String>,
+    /// Add the name of the thread each record was logged from, or its
+    /// numeric ID if it is unnamed, as set by --log-threads. See
+    /// worker_thread_name() for naming threads.
+    pub show_thread: bool,
+    /// Start each log line with the time since logging was set up, e.g.
+    /// '[+02:13:45]', as set by --log-elapsed. This is in addition to
+    /// timestamps, unless they are Off.
+    pub elapsed: bool,
+    /// Log the command line, working directory, host and time along with
+    /// the program name and version. See log_program_invocation(). Off by
+    /// default, since the command line may contain paths or other details
+    /// which users do not expect to appear in logs.
+    pub log_invocation: bool,
+    /// How many of the first warnings and errors to keep, for
+    /// log_warning_summary() and retained_warnings().
+    pub retained_warnings: usize,
+    /// Log panics as errors, see install_panic_logging_hook().
+    pub log_panics: bool,
+    /// Test mode, for running a tool's command line code repeatedly in the
+    /// same process, e.g. from integration tests. Log output is captured by
+    /// the test harness, and installing a logger when one has already been
+    /// installed is not an error. Also enabled by setting the
+    /// TEST_LOGGING_ENV_VAR environment variable, so that tests need not
+    /// change the options a tool uses.
+    pub test_mode: bool,
+}
+
+/// Environment variable the log level is read from by default, e.g.
+/// 'BIRD_TOOL_LOG_LEVEL=debug' to log as if --verbose was given.
+pub const DEFAULT_LOG_LEVEL_ENV_VAR: &str = "BIRD_TOOL_LOG_LEVEL";
+
+/// Environment variable which, when set, turns on LoggingOptions::test_mode.
+pub const TEST_LOGGING_ENV_VAR: &str = "BIRD_TOOL_TEST_LOGGING";
+
+impl Default for LoggingOptions {
+    fn default() -> Self {
+        LoggingOptions {
+            timestamps: LogTimestamps::default(),
+            color: LogColor::default(),
+            target: LogTarget::default(),
+            log_file: None,
+            log_file_append: false,
+            format: LogFormat::default(),
+            module_filters: vec![],
+            filter_env_var: None,
+            log_level_env_var: Some(DEFAULT_LOG_LEVEL_ENV_VAR.to_string()),
+            show_thread: false,
+            elapsed: false,
+            log_invocation: false,
+            retained_warnings: 1,
+            log_panics: false,
+            test_mode: false,
+        }
+    }
+}
+
+/// Whether log output is coloured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogColor {
+    /// Colour only when writing to a terminal, and the NO_COLOR environment
+    /// variable is unset.
+    #[default]
+    Auto,
+    /// Colour even when not writing to a terminal, e.g. for viewing with
+    /// 'less -R'. Output is still not coloured when there is a log file or
+    /// the LogTarget is a File.
+    Always,
+    Never,
+}
+
+/// Where log output is written.
+#[derive(Debug, Clone, Default)]
+pub enum LogTarget {
+    #[default]
+    Stderr,
+    /// Stdout, for wrappers which expect diagnostics there rather than on
+    /// stderr.
+    Stdout,
+    /// A file which is already open, e.g. one made from a file descriptor
+    /// passed by a wrapper. Log output to it is never coloured.
+    File(Arc<std::fs::File>),
+    /// Stderr, with the progress bars of the MultiProgress hidden while each
+    /// record is written, so that records appear above them rather than
+    /// mangling them. See progress::multi_progress().
+    #[cfg(feature = "progress")]
+    Progress(indicatif::MultiProgress),
+}
+
+/// Format of each log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human readable lines, as formatted by env_logger.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields level, target, message,
+    /// timestamp (unless timestamps are Off), elapsed_seconds (if elapsed),
+    /// thread (if show_thread), program and version, for parsing by
+    /// workflow systems.
+    Json,
+}
+
+/// Precision of the RFC 3339 timestamp at the start of each log line, e.g.
+/// '2024-01-31T12:34:56.789Z' for Millis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogTimestamps {
+    /// No timestamps.
+    Off,
+    /// Whole seconds, as env_logger does by default.
+    #[default]
+    Seconds,
+    Millis,
+    Micros,
+}
+
+impl LogTimestamps {
+    pub(crate) fn precision(&self) -> Option<env_logger::TimestampPrecision> {
+        match self {
+            LogTimestamps::Off => None,
+            LogTimestamps::Seconds => Some(env_logger::TimestampPrecision::Seconds),
+            LogTimestamps::Millis => Some(env_logger::TimestampPrecision::Millis),
+            LogTimestamps::Micros => Some(env_logger::TimestampPrecision::Micros),
+        }
+    }
+}
+
+/// Whether log output should be coloured, along with the reason why. It is
+/// never coloured when written to a file. Otherwise LogColor::Always or Never
+/// take precedence over the NO_COLOR environment variable, which takes
+/// precedence over whether the target is a terminal.
+fn log_write_style(
+    color: LogColor,
+    target: &LogTarget,
+    log_file: bool,
+) -> (WriteStyle, &'static str) {
+    if let LogTarget::File(_) = target {
+        return (WriteStyle::Never, "it is written to a file");
+    } else if log_file {
+        return (WriteStyle::Never, "it is also written to a log file");
+    }
+    match color {
+        LogColor::Always => return (WriteStyle::Always, "colour was requested"),
+        LogColor::Never => return (WriteStyle::Never, "colour was turned off"),
+        LogColor::Auto => {}
+    }
+    let (is_terminal, terminal, not_terminal) = match target {
+        LogTarget::Stdout => (
+            std::io::stdout().is_terminal(),
+            "stdout is a terminal",
+            "stdout is not a terminal",
+        ),
+        _ => (
+            std::io::stderr().is_terminal(),
+            "stderr is a terminal",
+            "stderr is not a terminal",
+        ),
+    };
+    if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        (WriteStyle::Never, "the NO_COLOR environment variable is set")
+    } else if !is_terminal {
+        (WriteStyle::Never, not_terminal)
+    } else {
+        (WriteStyle::Auto, terminal)
+    }
+}
+
+/// Open the log file, creating its directory if needed.
+fn open_log_file(path: &Path, append: bool) -> std::io::Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+}
+
+/// The env_logger target which writes to target, and to the log file if
+/// there is one.
+fn env_logger_target(target: &LogTarget, log_file: Option<std::fs::File>) -> env_logger::Target {
+    match (target, log_file) {
+        (LogTarget::Stderr, None) => env_logger::Target::Stderr,
+        (LogTarget::Stdout, None) => env_logger::Target::Stdout,
+        (target, file) => env_logger::Target::Pipe(Box::new(LogWriter {
+            target: target.clone(),
+            file,
+        })),
+    }
+}
+
+/// Log target which writes to the LogTarget, and to the log file if there is
+/// one.
+struct LogWriter {
+    target: LogTarget,
+    file: Option<std::fs::File>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let result = match &self.target {
+            LogTarget::Stderr => std::io::stderr().write_all(buf),
+            LogTarget::Stdout => std::io::stdout().write_all(buf),
+            LogTarget::File(file) => (&**file).write_all(buf),
+            #[cfg(feature = "progress")]
+            LogTarget::Progress(multi) => multi.suspend(|| std::io::stderr().write_all(buf)),
+        };
+        match &mut self.file {
+            // Failing to write to the target e.g. because stderr was closed
+            // should not stop the log file being written.
+            Some(file) => file.write_all(buf)?,
+            None => result?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let result = match &self.target {
+            LogTarget::Stderr => std::io::stderr().flush(),
+            LogTarget::Stdout => std::io::stdout().flush(),
+            LogTarget::File(file) => (&**file).flush(),
+            #[cfg(feature = "progress")]
+            LogTarget::Progress(_) => std::io::stderr().flush(),
+        };
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => result,
+        }
+    }
+}
+
+/// The parts of LoggingOptions which affect how each record is written, when
+/// env_logger's default format is not used.
+#[derive(Clone, Copy)]
+struct RecordFormat {
+    timestamps: LogTimestamps,
+    show_thread: bool,
+    /// When the program started, if the elapsed time is shown.
+    start: Option<Instant>,
+}
+
+/// Write record in the same way as env_logger's default format, optionally
+/// with the elapsed time before it and the name of the current thread after
+/// the target, e.g.
+/// '[+12.345s] [2024-01-31T12:34:56Z INFO  mytool::module worker-3] Message'.
+fn write_text_log_record(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+    format: RecordFormat,
+) -> std::io::Result<()> {
+    if let Some(start) = format.start {
+        write!(buf, "[{}] ", format_elapsed(start.elapsed()))?;
+    }
+    let level_style = buf.default_level_style(record.level());
+    let mut header = vec![];
+    if let Some(timestamp) = record_timestamp(buf, format.timestamps) {
+        header.push(timestamp);
+    }
+    header.push(format!("{}{:<5}{:#}", level_style, record.level(), level_style));
+    if !record.target().is_empty() {
+        header.push(record.target().to_string());
+    }
+    if format.show_thread {
+        header.push(current_thread_name());
+    }
+    // Continuation lines are indented, as by env_logger.
+    let message = record.args().to_string().replace('\n', "\n    ");
+    writeln!(buf, "[{}] {}", header.join(" "), message)
+}
+
+/// Format the time elapsed since the program started, e.g. '+12.345s' below
+/// a minute, '+13:45' below an hour, and '+02:13:45' above.
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match seconds {
+        0..=59 => format!("+{}.{:03}s", seconds, elapsed.subsec_millis()),
+        60..=3599 => format!("+{:02}:{:02}", seconds / 60, seconds % 60),
+        _ => format!(
+            "+{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        ),
+    }
+}
+
+/// Name of the current thread, or its numeric ID if it is unnamed.
+fn current_thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => {
+            // ThreadId has no stable numeric accessor, so take the number
+            // from e.g. 'ThreadId(5)'.
+            let id = format!("{:?}", thread.id());
+            id.trim_start_matches("ThreadId(").trim_end_matches(')').to_string()
+        }
+    }
+}
+
+/// A name for the worker thread with the given index, e.g. 'worker-3', so
+/// that log records from it can be told apart with
+/// LoggingOptions::show_thread. This has the signature rayon expects, so can
+/// be passed to rayon::ThreadPoolBuilder::thread_name().
+pub fn worker_thread_name(index: usize) -> String {
+    format!("worker-{}", index)
+}
+
+/// The timestamp of a record being formatted, if there is one.
+fn record_timestamp(
+    buf: &env_logger::fmt::Formatter,
+    timestamps: LogTimestamps,
+) -> Option<String> {
+    match timestamps {
+        LogTimestamps::Off => None,
+        LogTimestamps::Seconds => Some(buf.timestamp_seconds().to_string()),
+        LogTimestamps::Millis => Some(buf.timestamp_millis().to_string()),
+        LogTimestamps::Micros => Some(buf.timestamp_micros().to_string()),
+    }
+}
+
+/// Write record as a line of JSON, as for LogFormat::Json.
+fn write_json_log_record(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+    format: RecordFormat,
+    program_name: &str,
+    version: &str,
+) -> std::io::Result<()> {
+    write!(
+        buf,
+        "{{\"level\":{},\"target\":{},\"message\":{}",
+        json_string(record.level().as_str()),
+        json_string(record.target()),
+        json_string(&record.args().to_string())
+    )?;
+    if let Some(timestamp) = record_timestamp(buf, format.timestamps) {
+        write!(buf, ",\"timestamp\":{}", json_string(&timestamp))?;
+    }
+    if let Some(start) = format.start {
+        write!(buf, ",\"elapsed_seconds\":{:.3}", start.elapsed().as_secs_f64())?;
+    }
+    if format.show_thread {
+        write!(buf, ",\"thread\":{}", json_string(&current_thread_name()))?;
+    }
+    writeln!(
+        buf,
+        ",\"program\":{},\"version\":{}}}",
+        json_string(program_name),
+        json_string(version)
+    )
+}
+
+/// Quote s as a JSON string. Control characters, including newlines, are
+/// escaped so that each record stays on one line.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reasons logging could not be set up by init_logging() or
+/// clap_utils::try_set_log_level_with_options().
+#[derive(Debug)]
+pub enum SetLogLevelError {
+    /// A logger has already been installed, e.g. by a library consumer or an
+    /// earlier call. This can usually be ignored, as logging still works.
+    AlreadyInitialised(log::SetLoggerError),
+    /// The log file could not be opened.
+    UnopenableLogFile {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    /// The environment variable the log level is read from is set to
+    /// something other than a log level.
+    InvalidLogLevel { variable: String, value: String },
+}
+
+impl std::fmt::Display for SetLogLevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SetLogLevelError::AlreadyInitialised(e) => write!(
+                f,
+                "Failed to set log level - has it been specified multiple times? Error was {}",
+                e
+            ),
+            SetLogLevelError::UnopenableLogFile { path, source } => write!(
+                f,
+                "Failed to open log file '{}': {}",
+                path.display(),
+                source
+            ),
+            SetLogLevelError::InvalidLogLevel { variable, value } => write!(
+                f,
+                "Invalid log level '{}' in the {} environment variable, expected one of off, error, warn, info, debug or trace",
+                value, variable
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SetLogLevelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SetLogLevelError::AlreadyInitialised(e) => Some(e),
+            SetLogLevelError::UnopenableLogFile { source, .. } => Some(source),
+            SetLogLevelError::InvalidLogLevel { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writer which keeps what is written, so that it can be read back.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    /// A logger made as by init_logging(), which writes to the returned
+    /// buffer rather than being installed.
+    fn captured_logger(
+        level: LevelFilter,
+        options: &LoggingOptions,
+        env_filters: Option<&str>,
+    ) -> (env_logger::Logger, SharedBuffer, Vec<String>) {
+        let buffer = SharedBuffer::default();
+        let (mut builder, filter_problems) =
+            logger_builder(level, "tool", "1.0", options, env_filters);
+        let logger = builder
+            .target(env_logger::Target::Pipe(Box::new(buffer.clone())))
+            .write_style(WriteStyle::Never)
+            .build();
+        (logger, buffer, filter_problems)
+    }
+
+    fn log_record(logger: &env_logger::Logger, level: log::Level, target: &str, message: &str) {
+        log::Log::log(
+            logger,
+            &log::Record::builder()
+                .args(format_args!("{}", message))
+                .level(level)
+                .target(target)
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_json_log_records_parse() {
+        let options = LoggingOptions {
+            format: LogFormat::Json,
+            show_thread: true,
+            elapsed: true,
+            ..Default::default()
+        };
+        let (logger, buffer, _) = captured_logger(LevelFilter::Trace, &options, None);
+        let message = "Genome \"a\\b.fna\"\nhas\ttabs, \u{1}control and ünïcödé";
+        log_record(&logger, log::Level::Warn, "tool::genomes", message);
+
+        let output = buffer.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(1, lines.len());
+        let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        let mut keys: Vec<&str> =
+            record.as_object().unwrap().keys().map(|key| key.as_str()).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            vec![
+                "elapsed_seconds",
+                "level",
+                "message",
+                "program",
+                "target",
+                "thread",
+                "timestamp",
+                "version"
+            ],
+            keys
+        );
+        assert_eq!("WARN", record["level"]);
+        assert_eq!("tool::genomes", record["target"]);
+        assert_eq!(message, record["message"]);
+        assert_eq!("tool", record["program"]);
+        assert_eq!("1.0", record["version"]);
+        assert!(record["timestamp"].as_str().unwrap().parse::<jiff::Timestamp>().is_ok());
+        assert!(record["elapsed_seconds"].is_f64());
+    }
+
+    #[test]
+    fn test_log_timestamps() {
+        let line = |timestamps| {
+            let options = LoggingOptions {
+                timestamps,
+                ..Default::default()
+            };
+            let (logger, buffer, _) = captured_logger(LevelFilter::Info, &options, None);
+            log_record(&logger, log::Level::Info, "tool", "Message");
+            buffer.contents()
+        };
+        assert_eq!("[INFO  tool] Message\n", line(LogTimestamps::Off));
+        // e.g. '[2024-01-31T12:34:56.789Z INFO  tool] Message'
+        for (timestamps, length) in [
+            (LogTimestamps::Seconds, 20),
+            (LogTimestamps::Millis, 24),
+            (LogTimestamps::Micros, 27),
+        ] {
+            let line = line(timestamps);
+            let (timestamp, rest) = line.strip_prefix('[').unwrap().split_once(' ').unwrap();
+            assert_eq!(length, timestamp.len(), "{}", line);
+            assert!(timestamp.parse::<jiff::Timestamp>().is_ok(), "{}", line);
+            assert_eq!("INFO  tool] Message\n", rest);
+        }
+        assert_eq!(LogTimestamps::Seconds, LoggingOptions::default().timestamps);
+    }
+
+    #[test]
+    fn test_init_logging_twice() {
+        let log = tempfile::NamedTempFile::new().unwrap();
+        let options = LoggingOptions {
+            target: LogTarget::File(Arc::new(log.reopen().unwrap())),
+            ..Default::default()
+        };
+        // No other test installs a logger.
+        init_logging(LevelFilter::Info, "tool", "1.0", &options, true).unwrap();
+        match init_logging(LevelFilter::Debug, "tool", "1.0", &options, true) {
+            Err(SetLogLevelError::AlreadyInitialised(_)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        ensure_logging(LevelFilter::Debug);
+        log::logger().flush();
+        let output = std::fs::read_to_string(log.path()).unwrap();
+        assert_eq!(1, output.matches("tool version 1.0").count(), "{}", output);
+    }
+
+    #[test]
+    fn test_module_filters() {
+        let options = LoggingOptions {
+            timestamps: LogTimestamps::Off,
+            module_filters: vec![("hyper".to_string(), LevelFilter::Warn)],
+            ..Default::default()
+        };
+        let (logger, buffer, _) = captured_logger(LevelFilter::Debug, &options, None);
+        log_record(&logger, log::Level::Debug, "hyper::client", "Noisy");
+        log_record(&logger, log::Level::Warn, "hyper::client", "Important");
+        log_record(&logger, log::Level::Debug, "tool", "Useful");
+        assert_eq!(
+            "[WARN  hyper::client] Important\n[DEBUG tool] Useful\n",
+            buffer.contents()
+        );
+
+        // RUST_LOG still wins
+        let (logger, buffer, _) =
+            captured_logger(LevelFilter::Debug, &options, Some("hyper=debug"));
+        log_record(&logger, log::Level::Debug, "hyper::client", "Noisy");
+        assert_eq!("[DEBUG hyper::client] Noisy\n", buffer.contents());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_log_targets() {
+        use std::io::Read;
+        use std::os::fd::OwnedFd;
+
+        assert!(matches!(
+            env_logger_target(&LogTarget::Stdout, None),
+            env_logger::Target::Stdout
+        ));
+
+        // Records written to a pipe, and also to a log file
+        let (mut reader, writer) = std::io::pipe().unwrap();
+        let target = LogTarget::File(Arc::new(std::fs::File::from(OwnedFd::from(writer))));
+        assert_eq!(WriteStyle::Never, log_write_style(LogColor::Always, &target, false).0);
+        let log = tempfile::NamedTempFile::new().unwrap();
+        let logger = logger_builder(LevelFilter::Info, "tool", "1.0", &Default::default(), None)
+            .0
+            .format_timestamp(None)
+            .target(env_logger_target(&target, Some(log.reopen().unwrap())))
+            .build();
+        log_record(&logger, log::Level::Info, "tool", "Message");
+        log_record(&logger, log::Level::Debug, "tool", "Hidden");
+        drop(logger);
+        drop(target);
+        let mut piped = String::new();
+        reader.read_to_string(&mut piped).unwrap();
+        assert_eq!("[INFO  tool] Message\n", piped);
+        assert_eq!(piped, std::fs::read_to_string(log.path()).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_env_filters_ignored() {
+        let options = LoggingOptions {
+            timestamps: LogTimestamps::Off,
+            ..Default::default()
+        };
+        let (logger, buffer, problems) = captured_logger(
+            LevelFilter::Info,
+            &options,
+            Some("debgu,hyper=loud,tool::genomes=debug"),
+        );
+        assert_eq!(
+            vec![
+                "'debgu' would be treated as a module name, but looks like the log level 'debug'",
+                "'loud' is not a log level",
+            ],
+            problems
+        );
+        // The valid filter is still applied.
+        log_record(&logger, log::Level::Debug, "tool::genomes", "Shown");
+        log_record(&logger, log::Level::Debug, "tool", "Hidden");
+        log_record(&logger, log::Level::Info, "hyper", "Info");
+        assert_eq!("[DEBUG tool::genomes] Shown\n[INFO  hyper] Info\n", buffer.contents());
+
+        let (_, _, problems) = captured_logger(LevelFilter::Info, &options, Some("a=b=c/x/y"));
+        assert_eq!(vec!["only one '/' is allowed"], problems);
+    }
+}
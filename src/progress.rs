@@ -0,0 +1,37 @@
+use std::io::IsTerminal;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Create a MultiProgress for showing progress bars on stderr, which are
+/// hidden if stderr is not a terminal. To stop log records mangling the bars,
+/// set up logging with it as the target, e.g.
+///
+/// ```ignore
+/// let multi = progress::multi_progress();
+/// let mut options = logging::LoggingOptions::default();
+/// options.target = logging::LogTarget::Progress(multi.clone());
+/// clap_utils::set_log_level_with_options(&m, true, "mytool", VERSION, &options);
+/// let bar = progress::add_progress_bar(&multi, genomes.len() as u64);
+/// ```
+pub fn multi_progress() -> MultiProgress {
+    match std::io::stderr().is_terminal() {
+        true => MultiProgress::with_draw_target(ProgressDrawTarget::stderr()),
+        false => {
+            debug!("Not showing progress bars, as stderr is not a terminal");
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        }
+    }
+}
+
+/// Add a progress bar counting up to length to multi, with the style used
+/// by tools built with this crate.
+pub fn add_progress_bar(multi: &MultiProgress, length: u64) -> ProgressBar {
+    let bar = multi.add(ProgressBar::new(length));
+    bar.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg} (ETA {eta})",
+        )
+        .expect("Invalid progress bar template"),
+    );
+    bar
+}